@@ -1,8 +1,13 @@
+use std::time::Duration;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum IOError {
     WouldBlock,
     SyscallResult(String),
     ConnectionClosed,
+    ConnectionReset,
+    ConnectionRefused,
+    TimedOut,
 }
 
 impl std::fmt::Display for IOError {
@@ -11,6 +16,148 @@ impl std::fmt::Display for IOError {
             IOError::WouldBlock => write!(f, "This operation would block."),
             IOError::SyscallResult(res) => write!(f, "{res}"),
             IOError::ConnectionClosed => write!(f, "Peer closed the connection."),
+            IOError::ConnectionReset => write!(f, "Connection reset by peer."),
+            IOError::ConnectionRefused => write!(f, "Connection refused."),
+            IOError::TimedOut => write!(f, "This operation timed out."),
+        }
+    }
+}
+
+// Error returned when awaiting a JoinHandle whose task did not complete
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinError {
+    Cancelled,
+    Panicked(String),
+}
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "Task was cancelled."),
+            JoinError::Panicked(msg) => write!(f, "Task panicked: {msg}"),
+        }
+    }
+}
+
+// Error returned by a timeout when the deadline is reached before the future completes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elapsed;
+
+impl std::fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Deadline has elapsed.")
+    }
+}
+
+impl From<Elapsed> for IOError {
+    fn from(_: Elapsed) -> Self {
+        IOError::TimedOut
+    }
+}
+
+// Error returned when sending on a channel whose receivers are gone. Holds the
+// value that could not be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendError<T>(pub T);
+
+impl<T> std::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Channel is closed.")
+    }
+}
+
+// Error returned by a send that would have to wait
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+impl<T> std::fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Channel is full."),
+            TrySendError::Closed(_) => write!(f, "Channel is closed."),
+        }
+    }
+}
+
+// Error returned when receiving from a channel fails
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecvError {
+    Closed,      // Every sender is gone and nothing is left to receive
+    Lagged(u64), // The receiver fell behind and missed that many values (broadcast only)
+}
+
+impl std::fmt::Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecvError::Closed => write!(f, "Channel is closed."),
+            RecvError::Lagged(missed) => write!(f, "Receiver lagged behind by {missed} values."),
+        }
+    }
+}
+
+// Error returned by a receive that would have to wait
+#[derive(Debug, Clone, PartialEq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+}
+
+impl std::fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "Channel is empty."),
+            TryRecvError::Closed => write!(f, "Channel is closed."),
+        }
+    }
+}
+
+// Error returned when accessing a task-local value outside of a scope setting it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessError;
+
+impl std::fmt::Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Task-local value is not set.")
+    }
+}
+
+// Error returned when a simulation fails, carrying the seed that replays it
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimError {
+    Deadlock { seed: u64, time: Duration }, // Tasks are left but none can progress
+    StepLimit { seed: u64, steps: u64 },    // Tasks kept running without the root completing
+    Panicked { seed: u64, message: String }, // The root future panicked
+}
+
+impl SimError {
+    // Get the seed of the failed simulation
+    pub fn seed(&self) -> u64 {
+        match self {
+            SimError::Deadlock { seed, .. }
+            | SimError::StepLimit { seed, .. }
+            | SimError::Panicked { seed, .. } => *seed,
+        }
+    }
+}
+
+impl std::fmt::Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimError::Deadlock { seed, time } => {
+                write!(f, "Simulation with seed {seed} deadlocked at {time:?}.")
+            }
+            SimError::StepLimit { seed, steps } => {
+                write!(
+                    f,
+                    "Simulation with seed {seed} did not complete within {steps} steps."
+                )
+            }
+            SimError::Panicked { seed, message } => {
+                write!(f, "Simulation with seed {seed} panicked: {message}")
+            }
         }
     }
 }
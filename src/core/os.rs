@@ -2,12 +2,15 @@ pub struct OS;
 
 impl OS {
     pub fn err_no() -> i32 {
-        let errno = unsafe { *libc::__errno_location() };
-        return errno;
+        unsafe { *libc::__errno_location() }
     }
 
     pub fn err_msg() -> String {
-        let errno = OS::err_no();
+        OS::strerror(OS::err_no())
+    }
+
+    // Describe the given error number
+    pub fn strerror(errno: i32) -> String {
         let cstr = unsafe { std::ffi::CStr::from_ptr(libc::strerror(errno)) };
         cstr.to_string_lossy().into_owned()
     }
@@ -1,16 +1,46 @@
-mod core;
-mod net;
-mod runtime;
+use std::time::Duration;
 
-use net::SocketAddrV4;
-use runtime::{executor, TcpListener, TcpStream};
+use toy_async_server::core::result::Result;
+use toy_async_server::net::SocketAddrV4;
+use toy_async_server::runtime::signal::{self, SignalKind};
+use toy_async_server::runtime::{executor, Builder, CancellationToken, TcpListener, TcpStream};
+use toy_async_server::task_local;
 
-use crate::core::result::Result;
+// Time a client may stay silent before its connection is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Time in-flight clients get to complete once the server shuts down
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+task_local! {
+    // Number of the connection being handled, for the logs
+    static CONNECTION: u64;
+}
 
 // Entry point of the application
 fn main() -> Result<()> {
-    // Run the asynchronous code block using the executor
-    executor::block_on(async {
+    // Leave the signals to the runtime, before any thread inheriting the mask starts
+    signal::block(&[
+        SignalKind::Interrupt,
+        SignalKind::Terminate,
+        SignalKind::Hangup,
+    ])?;
+
+    // Run one worker thread per available core
+    let runtime = Builder::new().thread_name("toy").build()?;
+
+    // Cancelled to stop accepting connections
+    let shutdown = CancellationToken::new();
+
+    // Run the asynchronous code block on the runtime
+    runtime.block_on(async move {
+        // Shut down on Ctrl-C or SIGTERM, report reload requests on SIGHUP
+        let signals = [
+            executor::spawn(stop_on(SignalKind::Interrupt, shutdown.clone())),
+            executor::spawn(stop_on(SignalKind::Terminate, shutdown.clone())),
+            executor::spawn(reload_on(SignalKind::Hangup, shutdown.clone())),
+        ];
+
         // Define the address to listen on (e.g., 0.0.0.0:8000)
         let addr = SocketAddrV4::new([0, 0, 0, 0], 8000);
 
@@ -18,23 +48,75 @@ fn main() -> Result<()> {
         let listener = TcpListener::bind(addr).unwrap();
         println!("[main] Started listening on {:?}", addr);
 
-        // Accept incoming connections and handle them asynchronously
-        loop {
-            let (mut stream, addr) = listener.accept().await.unwrap();
+        // Accept incoming connections and handle them asynchronously until shutdown
+        let mut connections = 0;
+        while let Some(accepted) = shutdown.run_until_cancelled(listener.accept()).await {
+            let (mut stream, addr) = accepted.unwrap();
+            connections += 1;
 
             // Spawn a new asynchronous task to handle the client
-            executor::spawn(async move {
+            executor::spawn(CONNECTION.scope(connections, async move {
                 if let Err(err) = handle_client(&mut stream, addr).await {
-                    eprintln!("[main] Error occurred while handling client: {}", err);
+                    eprintln!(
+                        "[main] Error occurred while handling client #{}: {}",
+                        CONNECTION.get(),
+                        err
+                    );
                 }
-            });
+            }));
         }
-    })
+
+        println!("[main] Stopped accepting connections");
+
+        // Let the signal tasks see the cancellation, so only clients are left to drain
+        for handle in signals {
+            let _ = handle.await;
+        }
+    })?;
+
+    // Let the clients being served finish, then drop the rest
+    let report = runtime.drain(DRAIN_TIMEOUT)?;
+    println!(
+        "[main] Shut down: {} connection(s) drained, {} aborted",
+        report.drained, report.aborted
+    );
+    Ok(())
+}
+
+// Cancel the token once the signal is received, or return once it is cancelled
+async fn stop_on(kind: SignalKind, shutdown: CancellationToken) {
+    let mut signal = signal::signal(kind).unwrap();
+
+    if let Some(received) = shutdown.run_until_cancelled(signal.recv()).await {
+        received.unwrap();
+        println!("[main] Got {:?}, shutting down", kind);
+        shutdown.cancel();
+    }
+}
+
+// Handle every delivery of the signal as a configuration reload request, until
+// the token is cancelled
+async fn reload_on(kind: SignalKind, shutdown: CancellationToken) {
+    let mut signal = signal::signal(kind).unwrap();
+
+    while let Some(received) = shutdown.run_until_cancelled(signal.recv()).await {
+        received.unwrap();
+        // The server has no configuration file yet, there is nothing to re-read
+        println!("[main] Got {:?}, nothing to reload", kind);
+    }
 }
 
 // Asynchronously handle a client connection
 async fn handle_client(stream: &mut TcpStream, addr: SocketAddrV4) -> Result<()> {
-    println!("[handle_client] Got connection on {:?}", addr);
+    println!(
+        "[handle_client] Got connection #{} on {:?}",
+        CONNECTION.get(),
+        addr
+    );
+
+    // Do not let slow clients hold the connection forever
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT));
 
     let mut incoming = vec![];
 
@@ -62,6 +144,10 @@ async fn handle_client(stream: &mut TcpStream, addr: SocketAddrV4) -> Result<()>
     stream.write(b"Hello from plaque!\n").await?;
 
     // Close the connection
-    println!("[handle_client] Closing connection for {:?}\n", addr);
+    println!(
+        "[handle_client] Closing connection #{} for {:?}\n",
+        CONNECTION.get(),
+        addr
+    );
     Ok(())
 }
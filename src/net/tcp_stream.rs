@@ -20,7 +20,7 @@ impl TcpStream {
     }
 
     // Read data from the stream.
-    pub fn read(&mut self, buff: &mut [u8]) -> Result<isize> {
+    pub fn read(&self, buff: &mut [u8]) -> Result<isize> {
         // Read data from the stream into the provided buffer.
         // Perform the read syscall and store the result in read_count.
         let read_count =
@@ -37,6 +37,9 @@ impl TcpStream {
             if errno == libc::EAGAIN || errno == libc::EWOULDBLOCK {
                 return Err(IOError::WouldBlock);
             }
+            if errno == libc::ECONNRESET {
+                return Err(IOError::ConnectionReset);
+            }
             return Err(IOError::SyscallResult(os::OS::err_msg()));
         }
 
@@ -44,7 +47,7 @@ impl TcpStream {
     }
 
     // Write data to the stream.
-    pub fn write(&mut self, buff: &[u8]) -> Result<isize> {
+    pub fn write(&self, buff: &[u8]) -> Result<isize> {
         // Perform the write syscall and store the result in write_count.
         let write_count =
             unsafe { libc::write(self.fd(), buff as *const _ as *const c_void, buff.len()) };
@@ -55,6 +58,9 @@ impl TcpStream {
             if errno == libc::EAGAIN || errno == libc::EWOULDBLOCK {
                 return Err(IOError::WouldBlock);
             }
+            if errno == libc::ECONNRESET {
+                return Err(IOError::ConnectionReset);
+            }
             return Err(IOError::SyscallResult(os::OS::err_msg()));
         }
 
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use super::join_handle::{self, JoinHandle};
+use super::threads::ThreadConfig;
+
+// Default limit of threads running blocking closures at the same time
+pub const DEFAULT_MAX_THREADS: usize = 512;
+
+// Default time an idle blocking thread waits for new work before exiting
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Struct representing an elastic pool of threads running blocking closures off
+// the executor. Threads are started on demand up to a limit and exit once they
+// stayed idle for the keep-alive duration.
+pub struct BlockingPool {
+    shared: Arc<Shared>,
+}
+
+// State shared between the pool and its threads
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,      // Used to hand jobs to idle threads
+    max_threads: usize,    // Maximum number of threads alive at once
+    keep_alive: Duration,  // Time an idle thread waits before exiting
+    threads: ThreadConfig, // Name and hooks of the pool threads
+}
+
+struct State {
+    queue: VecDeque<Job>, // Jobs waiting for a thread
+    threads: usize,       // Number of threads alive
+    idle: usize,          // Number of threads waiting for a job
+    notified: usize,      // Number of idle threads notified but not yet awake
+    shutdown: bool,       // Set once the pool is dropped
+}
+
+impl BlockingPool {
+    // Constructor to create a pool running at most `max_threads` closures at once
+    pub fn new(max_threads: usize, keep_alive: Duration, threads: ThreadConfig) -> Self {
+        assert!(max_threads > 0, "a blocking pool needs at least one thread");
+
+        BlockingPool {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    queue: VecDeque::new(),
+                    threads: 0,
+                    idle: 0,
+                    notified: 0,
+                    shutdown: false,
+                }),
+                condvar: Condvar::new(),
+                max_threads,
+                keep_alive,
+                threads,
+            }),
+        }
+    }
+
+    // Function to run a blocking closure on the pool, returning a handle to await its output
+    pub fn spawn<F, R>(&self, f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (job, handle) = join_handle::blocking(f);
+        if let Err(job) = self.execute(job) {
+            // No thread can run the closure: cancel it so awaiting the handle
+            // resolves to JoinError::Cancelled instead of waiting forever
+            handle.abort();
+            job();
+        }
+        handle
+    }
+
+    // Queue a job and hand it to an idle thread, or start a new one if allowed.
+    // Gives the job back if no thread is alive to run it and none can be started.
+    fn execute(&self, job: Job) -> std::result::Result<(), Job> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queue.push_back(job);
+
+        if state.idle > 0 {
+            state.idle -= 1;
+            state.notified += 1;
+            self.shared.condvar.notify_one();
+        } else if state.threads < self.shared.max_threads {
+            state.threads += 1;
+
+            let shared = self.shared.clone();
+            if let Err(err) = self.shared.threads.spawn("blocking", move || shared.run()) {
+                println!("[blocking] failed to start a thread: {err}");
+                state.threads -= 1;
+
+                // The threads still alive pick the job up once they are done
+                if state.threads == 0 {
+                    return Err(state.queue.pop_back().unwrap());
+                }
+            }
+        }
+        // Otherwise the job waits for a busy thread to finish
+        Ok(())
+    }
+}
+
+impl Shared {
+    // Function to run queued jobs until the thread stays idle for too long
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(job) = state.queue.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            if state.shutdown {
+                break;
+            }
+
+            state.idle += 1;
+            let (guard, timeout) = self.condvar.wait_timeout(state, self.keep_alive).unwrap();
+            state = guard;
+
+            if state.notified > 0 {
+                // The notifier already took us off the idle count
+                state.notified -= 1;
+            } else {
+                state.idle -= 1;
+                if timeout.timed_out() && state.queue.is_empty() {
+                    break;
+                }
+            }
+        }
+
+        state.threads -= 1;
+    }
+}
+
+impl Default for BlockingPool {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAX_THREADS,
+            DEFAULT_KEEP_ALIVE,
+            ThreadConfig::default(),
+        )
+    }
+}
+
+impl Drop for BlockingPool {
+    // Let the threads exit once the queued jobs are done
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.condvar.notify_all();
+    }
+}
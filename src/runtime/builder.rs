@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::blocking::{self, BlockingPool};
+use super::multi_thread::{self, MultiThread};
+use super::polling::Backend;
+use super::reactor::Reactor;
+use super::runtime::Runtime;
+use super::task::RemainingTasks;
+use super::threads::ThreadConfig;
+use crate::core::result::Result;
+
+// Default number of events the reactor handles per wait
+pub const DEFAULT_EVENT_BATCH_SIZE: usize = 1024;
+
+// Struct used to configure a Runtime before creating it
+pub struct Builder {
+    worker_threads: usize,       // Number of worker threads of the pool
+    queue_capacity: usize,       // Tasks a worker queues locally before using the injector
+    event_batch_size: usize,     // Events the reactor handles per wait
+    backend: Option<Backend>, // Event notification backend driving the reactor, None for the default
+    max_blocking_threads: usize, // Limit of threads running blocking closures
+    thread_keep_alive: Duration, // Time an idle blocking thread is kept around
+    threads: ThreadConfig,    // Name and hooks of the runtime threads
+    remaining: RemainingTasks, // What block_on does with the tasks left once it completes
+}
+
+impl Builder {
+    // Constructor to create a Builder with one worker per core and the default backend
+    pub fn new() -> Self {
+        Builder {
+            worker_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_capacity: multi_thread::DEFAULT_LOCAL_QUEUE_CAPACITY,
+            event_batch_size: DEFAULT_EVENT_BATCH_SIZE,
+            backend: None,
+            max_blocking_threads: blocking::DEFAULT_MAX_THREADS,
+            thread_keep_alive: blocking::DEFAULT_KEEP_ALIVE,
+            threads: ThreadConfig::default(),
+            remaining: RemainingTasks::default(),
+        }
+    }
+
+    // Set the number of worker threads
+    pub fn worker_threads(&mut self, workers: usize) -> &mut Self {
+        assert!(workers > 0, "a runtime needs at least one worker thread");
+        self.worker_threads = workers;
+        self
+    }
+
+    // Set how many tasks a worker queues locally before handing them to the others
+    pub fn queue_capacity(&mut self, capacity: usize) -> &mut Self {
+        assert!(
+            capacity > 0,
+            "a worker queue needs room for at least one task"
+        );
+        self.queue_capacity = capacity;
+        self
+    }
+
+    // Set the maximum number of events the reactor handles per wait
+    pub fn event_batch_size(&mut self, size: usize) -> &mut Self {
+        assert!(size > 0, "the reactor needs room for at least one event");
+        self.event_batch_size = size;
+        self
+    }
+
+    // Set the event notification backend, e.g. Backend::Poll where epoll is forbidden
+    pub fn backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    // Set the maximum number of threads running spawn_blocking closures at once
+    pub fn max_blocking_threads(&mut self, threads: usize) -> &mut Self {
+        assert!(threads > 0, "a runtime needs at least one blocking thread");
+        self.max_blocking_threads = threads;
+        self
+    }
+
+    // Set how long an idle blocking thread waits for new work before exiting
+    pub fn thread_keep_alive(&mut self, keep_alive: Duration) -> &mut Self {
+        self.thread_keep_alive = keep_alive;
+        self
+    }
+
+    // Set the prefix of the thread names, e.g. `{name}-worker-0` and `{name}-blocking`
+    pub fn thread_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.threads.name = name.into();
+        self
+    }
+
+    // Set a callback run on every runtime thread when it starts
+    pub fn on_thread_start<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.threads.on_start = Some(Arc::new(f));
+        self
+    }
+
+    // Set a callback run on every runtime thread before it stops
+    pub fn on_thread_stop<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.threads.on_stop = Some(Arc::new(f));
+        self
+    }
+
+    // Set what block_on does with the spawned tasks still running once its Future completes
+    pub fn remaining_tasks(&mut self, remaining: RemainingTasks) -> &mut Self {
+        self.remaining = remaining;
+        self
+    }
+
+    // Create the runtime and start its workers, failing if the backend set is
+    // not available. Without one, the default backend falls back to epoll.
+    pub fn build(&self) -> Result<Runtime> {
+        let reactor = match self.backend {
+            Some(backend) => Reactor::with_backend(backend, self.event_batch_size)?,
+            None => Reactor::with_default_backend(self.event_batch_size)?,
+        };
+        let blocking = BlockingPool::new(
+            self.max_blocking_threads,
+            self.thread_keep_alive,
+            self.threads.clone(),
+        );
+        let config = multi_thread::Config {
+            workers: self.worker_threads,
+            local_queue_capacity: self.queue_capacity,
+            threads: self.threads.clone(),
+        };
+
+        Runtime::start(
+            MultiThread::with_config(config, reactor, blocking),
+            self.remaining,
+        )
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
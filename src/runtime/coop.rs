@@ -0,0 +1,83 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// Number of operations the runtime I/O futures complete for a task in a single
+// poll. Past it they return Pending, so a task always finding data ready gives
+// the other tasks their turn instead of looping forever.
+pub const BUDGET: u32 = 128;
+
+// Number of task polls after which an executor kept busy looks for I/O events
+// without blocking, so tasks woken by I/O are not starved by ready ones
+pub const EVENT_INTERVAL: usize = 61;
+
+// Define a thread-local variable holding the operations left to the task being
+// polled on the current thread. None outside of a task poll: no limit.
+thread_local! {
+    static REMAINING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Function to run the poll of a task with a fresh budget, restoring the one of
+// the caller afterwards, e.g. for a block_on nested in a task
+pub fn budget<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<u32>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REMAINING.with(|remaining| remaining.set(self.0));
+        }
+    }
+
+    let _restore = Restore(REMAINING.with(|remaining| remaining.replace(Some(BUDGET))));
+    f()
+}
+
+// Function to poll an operation of a runtime future against the budget of the
+// current task. Once the budget is spent, the task is woken and Pending is
+// returned without trying; a completed operation spends one unit.
+pub fn poll_budgeted<T>(
+    cx: &mut Context<'_>,
+    poll: impl FnOnce(&mut Context<'_>) -> Poll<T>,
+) -> Poll<T> {
+    if REMAINING.with(|remaining| remaining.get()) == Some(0) {
+        cx.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+
+    let output = poll(cx);
+    if output.is_ready() {
+        REMAINING.with(|remaining| remaining.set(remaining.get().map(|left| left - 1)));
+    }
+    output
+}
+
+// Check whether the task being polled may still complete operations
+pub fn has_budget_remaining() -> bool {
+    REMAINING.with(|remaining| remaining.get()) != Some(0)
+}
+
+// Function to give the other tasks a turn: the task is queued again and
+// resumes once the executor gets back to it
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+// Future returning Pending once, waking its task right away
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -1,83 +1,289 @@
+use std::cell::{Cell, RefCell};
 use std::future::Future;
-use std::sync::Mutex;
-use std::task::Poll::{Pending, Ready};
-use std::task::{Context, Waker};
-use std::{cell::RefCell, sync::Arc};
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
 
-use super::reactor::REACTOR;
-use super::task::Task;
+use super::blocking::BlockingPool;
+use super::coop;
+use super::join_handle::{self, JoinHandle};
+use super::local::LocalSet;
+use super::priority::{ClassMetrics, Priority, PriorityStats};
+use super::reactor::{Reactor, REACTOR};
+use super::task::{OwnedTasks, RemainingTasks, Schedule, Task};
 use super::task_queue::TaskQueue;
+use crate::core::error::{IOError, JoinError};
 use crate::core::result::Result;
 
 // Define a thread-local variable to hold the Executor instance
 thread_local! {
-    static EXECUTOR: RefCell<Executor> = RefCell::new(Executor::new());
+    pub static EXECUTOR: RefCell<Executor> = RefCell::new(Executor::new());
 }
 
-// Function to block the current thread and run a Future to completion
-pub fn block_on<F>(f: F) -> Result<()>
+// Define a thread-local variable holding the scheduler of the multi-threaded
+// pool the current thread works for, if any
+thread_local! {
+    static CONTEXT: RefCell<Option<Arc<dyn Schedule>>> = const { RefCell::new(None) };
+}
+
+// Define a thread-local variable holding the blocking pool of the multi-threaded
+// pool the current thread works for, if any
+thread_local! {
+    static BLOCKING: RefCell<Option<Arc<BlockingPool>>> = const { RefCell::new(None) };
+}
+
+// Function to make spawns on the current thread go to the given scheduler and
+// blocking closures to the given pool
+pub fn enter(scheduler: Arc<dyn Schedule>, blocking: Arc<BlockingPool>) {
+    CONTEXT.with(|context| *context.borrow_mut() = Some(scheduler));
+    BLOCKING.with(|current| *current.borrow_mut() = Some(blocking));
+}
+
+// Function to block the current thread and run a Future to completion, returning
+// its output. Spawned tasks still running are handled as set_remaining_tasks says.
+pub fn block_on<F>(f: F) -> Result<F::Output>
 where
-    F: Future<Output = ()> + Send + 'static,
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
 {
-    EXECUTOR.with(|executor| -> Result<()> {
+    EXECUTOR.with(|executor| {
         let executor = executor.borrow();
-        executor.spawn(f); // Spawn the Future onto the Executor
-        executor.run() // Run the Executor to completion
+        let root = executor.spawn(f); // Spawn the Future onto the Executor
+        executor.run_until(root, &executor.local) // Run the Executor until the Future completes
     })
 }
 
-// Function to spawn a Future onto the Executor
-pub fn spawn<F>(f: F)
+// Function to choose what block_on does with the tasks still running once its
+// Future completes on the current thread. Detached by default.
+pub fn set_remaining_tasks(remaining: RemainingTasks) {
+    EXECUTOR.with(|executor| executor.borrow().remaining.set(remaining));
+}
+
+// Function to spawn a Future onto the Executor, returning a handle to await its output
+pub fn spawn<F>(f: F) -> JoinHandle<F::Output>
 where
-    F: Future<Output = ()> + Send + 'static,
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
 {
+    spawn_with_priority(Priority::Normal, f)
+}
+
+// Function to spawn a Future in the given scheduling class, returning a handle
+// to await its output
+pub fn spawn_with_priority<F>(priority: Priority, f: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    // Inside a simulation, spawn onto it so the run replays
+    if super::sim::is_current() {
+        return super::sim::spawn(f);
+    }
+
+    // Inside a worker thread, spawn onto the pool it belongs to
+    if let Some(scheduler) = CONTEXT.with(|context| context.borrow().clone()) {
+        let (task, handle) = join_handle::joinable(f, scheduler, priority);
+        task.schedule();
+        return handle;
+    }
+
     EXECUTOR.with(|executor| {
         let executor = executor.borrow();
-        executor.spawn(f); // Spawn the Future onto the Executor
-    });
+        executor.spawn_with_priority(priority, f) // Spawn the Future onto the Executor
+    })
+}
+
+// Get the scheduling activity of a class on the Executor of the current thread
+pub fn class_metrics(priority: Priority) -> ClassMetrics {
+    EXECUTOR.with(|executor| executor.borrow().class_metrics(priority))
+}
+
+// Function to spawn a Future that may not be Send onto the current thread,
+// returning a handle to await its output. Panics outside of executor::block_on
+// and LocalSet::block_on.
+pub fn spawn_local<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    super::local::spawn_local(f)
+}
+
+// Function to run a blocking closure on the blocking pool instead of the
+// executor, returning a handle to await its output
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    // Inside a worker thread, use the blocking pool of the pool it belongs to
+    if let Some(pool) = BLOCKING.with(|current| current.borrow().clone()) {
+        return pool.spawn(f);
+    }
+
+    EXECUTOR.with(|executor| executor.borrow().blocking.spawn(f))
 }
 
 // Struct representing an asynchronous task Executor
 pub struct Executor {
-    tasks: TaskQueue, // Queue to hold tasks (Futures)
+    tasks: Arc<TaskQueue>,           // Queue to hold tasks (Futures)
+    scheduler: Arc<Scheduler>,       // Scheduler handed to the spawned tasks
+    blocking: BlockingPool,          // Threads running the closures of spawn_blocking
+    remaining: Cell<RemainingTasks>, // What to do with the tasks left once block_on completes
+    local: LocalSet,                 // Tasks spawned with spawn_local during block_on
+}
+
+// Scheduler of the Executor: tasks are pushed onto its queue, and a wakeup from
+// another thread interrupts the reactor wait the Executor may be blocked in
+struct Scheduler {
+    queue: Arc<TaskQueue>,
+    reactor: Arc<Reactor>, // Reactor of the thread running the Executor
+    owner: ThreadId,       // Thread running the Executor
+    owned: OwnedTasks,     // Tasks spawned onto the Executor and not completed yet
+    stats: PriorityStats,  // Scheduling activity of every class
+}
+
+impl Schedule for Scheduler {
+    fn schedule(&self, task: Arc<Task>) {
+        self.stats.scheduled(task.priority);
+        self.queue.push(task); // Push the task onto the queue of its class
+
+        if thread::current().id() != self.owner {
+            self.reactor.unpark();
+        }
+    }
+
+    fn bind(&self, task: &Arc<Task>) {
+        self.owned.insert(task);
+    }
+
+    fn release(&self, task: &Arc<Task>) {
+        self.owned.remove(task);
+    }
 }
 
 impl Executor {
     pub fn new() -> Self {
+        let tasks = Arc::new(TaskQueue::new()); // Initialize the task queue
+        let scheduler = Arc::new(Scheduler {
+            queue: tasks.clone(),
+            reactor: REACTOR.with(|current| current.borrow().clone()),
+            owner: thread::current().id(),
+            owned: OwnedTasks::default(),
+            stats: PriorityStats::default(),
+        });
+
         Executor {
-            tasks: TaskQueue::new(), // Initialize the task queue
+            tasks,
+            scheduler,
+            blocking: BlockingPool::default(),
+            remaining: Cell::new(RemainingTasks::default()),
+            local: LocalSet::new(),
         }
     }
 
     // Function to spawn a Future onto the Executor
-    pub fn spawn<F>(&self, f: F)
+    pub fn spawn<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_with_priority(Priority::Normal, f)
+    }
+
+    // Function to spawn a Future onto the Executor in the given scheduling class
+    pub fn spawn_with_priority<F>(&self, priority: Priority, f: F) -> JoinHandle<F::Output>
     where
-        F: Future<Output = ()> + Send + 'static,
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
     {
-        self.tasks.send(Arc::new(Task {
-            future: Mutex::new(Box::pin(f)), // Wrap the Future in a Mutex for synchronization
-            sender: self.tasks.sender().clone(),
-        }));
+        let (task, handle) = join_handle::joinable(f, self.scheduler.clone(), priority);
+        task.schedule();
+        handle
+    }
+
+    // Get the scheduling activity of a class
+    pub fn class_metrics(&self, priority: Priority) -> ClassMetrics {
+        let queued = self.scheduler.queue.len_of(priority);
+        self.scheduler.stats.snapshot(priority, queued)
+    }
+
+    // Function to run the Executor and the LocalSet until the root task
+    // completes, returning its output. spawn_local uses the LocalSet meanwhile.
+    pub fn run_until<T>(&self, mut root: JoinHandle<T>, local: &LocalSet) -> Result<T> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let _entered = local.enter();
+
+        let output = loop {
+            self.run_ready(local)?;
+            if let Poll::Ready(output) = Pin::new(&mut root).poll(&mut cx) {
+                break output;
+            }
+
+            // Wait for I/O events from the reactor
+            self.wait_for_io()?;
+        };
+
+        match self.remaining.get() {
+            RemainingTasks::Detach => {}
+            RemainingTasks::Cancel => {
+                self.scheduler.owned.cancel_all();
+                local.cancel_all();
+            }
+            RemainingTasks::Wait => loop {
+                self.run_ready(local)?;
+                if self.scheduler.owned.is_empty() && local.is_empty() {
+                    break;
+                }
+                self.wait_for_io()?;
+            },
+        }
+
+        match output {
+            Ok(output) => Ok(output),
+            Err(JoinError::Panicked(msg)) => panic::resume_unwind(Box::new(msg)),
+            // Cancelled along with the other tasks, e.g. by a nested block_on
+            // set to cancel the tasks left once it completes
+            Err(JoinError::Cancelled) => Err(IOError::SyscallResult(
+                "the block_on future was cancelled".to_string(),
+            )),
+        }
     }
 
-    // Function to run the Executor and process tasks
-    pub fn run(&self) -> Result<()> {
+    // Function to poll every task queued so far, local or not, until none is
+    // left. While tasks keep waking each other, e.g. with yield_now, the reactor
+    // is checked every EVENT_INTERVAL polls so tasks waiting on I/O get a turn.
+    fn run_ready(&self, local: &LocalSet) -> Result<()> {
         loop {
+            let mut polled = local.run_ready();
+
             // Process tasks from the queue and dispatch them
-            while let Ok(task) = self.tasks.receiver().try_recv() {
-                let waker = task.waker();
-                let mut cx = Context::from_waker(&waker);
+            while polled < coop::EVENT_INTERVAL {
+                let Some(task) = self.tasks.pop() else {
+                    break;
+                };
                 println!("[Ex] Received Task polling Future ...");
-                match task.future.lock().unwrap().as_mut().poll(&mut cx) {
-                    Ready(_) => {
-                        println!("[Ex] Poll ready complete on spawned task");
-                    }
-                    Pending => {}
+                polled += 1;
+
+                let started = Instant::now();
+                let finished = task.poll();
+                let elapsed = started.elapsed();
+                self.scheduler.stats.polled(task.priority, elapsed);
+                task.poll_times.record(elapsed);
+                if finished {
+                    println!("[Ex] Poll ready complete on spawned task");
                 }
             }
 
-            // Wait for I/O events from the reactor
-            self.wait_for_io()?;
+            if polled == 0 {
+                return Ok(());
+            }
+            if polled >= coop::EVENT_INTERVAL {
+                self.poll_io()?;
+            }
         }
     }
 
@@ -86,7 +292,7 @@ impl Executor {
         println!("[wait_for_io] Waiting for the I/O events.");
         REACTOR.with(|current| -> Result<()> {
             let wakers: Vec<Waker> = {
-                let mut current = current.borrow_mut();
+                let current = current.borrow();
                 current.poll_wait()? // Poll for I/O events and get associated wakers
             };
             for waker in wakers {
@@ -96,4 +302,43 @@ impl Executor {
             Ok(())
         })
     }
+
+    // Function to wake the tasks whose I/O events are already available, without blocking
+    fn poll_io(&self) -> Result<()> {
+        let wakers = REACTOR.with(|current| current.borrow().poll_now())?;
+        for waker in wakers {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{block_on, set_remaining_tasks};
+    use crate::core::error::IOError;
+    use crate::runtime::task::RemainingTasks;
+    use crate::runtime::yield_now;
+
+    #[test]
+    fn nested_block_on_cancelling_the_root() {
+        let outer = block_on(async {
+            set_remaining_tasks(RemainingTasks::Cancel);
+            let inner = block_on(async { 1 });
+            set_remaining_tasks(RemainingTasks::Detach);
+
+            // Cancelled by the nested block_on, the root is dropped at its next await
+            assert_eq!(inner.unwrap(), 1);
+            yield_now().await;
+            unreachable!("the root task was cancelled");
+        });
+
+        assert!(matches!(outer, Err(IOError::SyscallResult(_))));
+    }
 }
@@ -0,0 +1,260 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::MaybeDone;
+
+// Function to run two futures concurrently, completing with both outputs once
+// both completed. The join! macro does the same for any number of futures.
+pub fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+    }
+}
+
+// Function to run two fallible futures concurrently, completing with both
+// values, or with the first error, in which case the other future is dropped.
+// The try_join! macro does the same for any number of futures.
+pub fn try_join<A, B, T, U, E>(a: A, b: B) -> TryJoin<A, B>
+where
+    A: Future<Output = Result<T, E>>,
+    B: Future<Output = Result<U, E>>,
+{
+    TryJoin {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+    }
+}
+
+// Future for joining two futures
+pub struct Join<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        // Poll both every time, either may have been woken
+        let a = state.a.poll_done(cx).is_ready();
+        let b = state.b.poll_done(cx).is_ready();
+        if !(a && b) {
+            return Poll::Pending;
+        }
+
+        Poll::Ready((
+            state.a.take_output().expect("Join polled after completion"),
+            state.b.take_output().expect("Join polled after completion"),
+        ))
+    }
+}
+
+// Future for joining two fallible futures
+pub struct TryJoin<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+impl<A, B, T, U, E> Future for TryJoin<A, B>
+where
+    A: Future<Output = Result<T, E>>,
+    B: Future<Output = Result<U, E>>,
+{
+    type Output = Result<(T, U), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        let a = state.a.poll_done(cx).is_ready();
+        if let Some(Err(_)) = state.a.output() {
+            state.b.cancel();
+            return Poll::Ready(Err(state.a.take_output().unwrap().err().unwrap()));
+        }
+
+        let b = state.b.poll_done(cx).is_ready();
+        if let Some(Err(_)) = state.b.output() {
+            state.a.cancel();
+            return Poll::Ready(Err(state.b.take_output().unwrap().err().unwrap()));
+        }
+
+        if !(a && b) {
+            return Poll::Pending;
+        }
+
+        let a = state
+            .a
+            .take_output()
+            .expect("TryJoin polled after completion");
+        let b = state
+            .b
+            .take_output()
+            .expect("TryJoin polled after completion");
+        Poll::Ready(Ok((a.ok().unwrap(), b.ok().unwrap())))
+    }
+}
+
+// Macro running any number of futures concurrently from an async context, and
+// evaluating to the tuple of their outputs once all completed:
+//
+//     let (a, b, c) = join!(fetch(1), fetch(2), time::sleep(delay));
+#[macro_export]
+macro_rules! join {
+    // Pair every future with the tuple fields before its own, "_" each
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* } $next:expr, $($rest:tt)*) => {
+        $crate::join!(@ { ( $($count)* _ ) $( ( $($skip)* ) $e, )* ( $($count)* ) $next, } $($rest)*)
+    };
+
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* }) => {{
+        let mut futures = ( $( $crate::runtime::future::MaybeDone::new($e), )* );
+
+        std::future::poll_fn(move |cx| {
+            let mut done = true;
+            $(
+                let ( $($skip,)* future, .. ) = &mut futures;
+                done &= future.poll_done(cx).is_ready();
+            )*
+            if !done {
+                return std::task::Poll::Pending;
+            }
+
+            std::task::Poll::Ready(( $({
+                let ( $($skip,)* future, .. ) = &mut futures;
+                future.take_output().expect("join! polled after completion")
+            }, )* ))
+        })
+        .await
+    }};
+
+    ($($e:expr),+ $(,)?) => {
+        $crate::join!(@ { () } $($e,)+)
+    };
+}
+
+// Macro running any number of fallible futures concurrently from an async
+// context, and evaluating to Ok with the tuple of their values once all
+// succeeded, or to the first error, dropping the futures still running:
+//
+//     let (user, posts) = try_join!(load_user(id), load_posts(id))?;
+#[macro_export]
+macro_rules! try_join {
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* } $next:expr, $($rest:tt)*) => {
+        $crate::try_join!(@ { ( $($count)* _ ) $( ( $($skip)* ) $e, )* ( $($count)* ) $next, } $($rest)*)
+    };
+
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* }) => {{
+        let mut futures = ( $( $crate::runtime::future::MaybeDone::new($e), )* );
+
+        std::future::poll_fn(move |cx| {
+            let mut done = true;
+            $(
+                let ( $($skip,)* future, .. ) = &mut futures;
+                if future.poll_done(cx).is_pending() {
+                    done = false;
+                } else if let Some(Err(_)) = future.output() {
+                    let err = future.take_output().unwrap().err().unwrap();
+                    return std::task::Poll::Ready(Err(err));
+                }
+            )*
+            if !done {
+                return std::task::Poll::Pending;
+            }
+
+            std::task::Poll::Ready(Ok(( $({
+                let ( $($skip,)* future, .. ) = &mut futures;
+                future.take_output().expect("try_join! polled after completion").ok().unwrap()
+            }, )* )))
+        })
+        .await
+    }};
+
+    ($($e:expr),+ $(,)?) => {
+        $crate::try_join!(@ { () } $($e,)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::ready;
+    use std::sync::atomic::Ordering;
+
+    use super::super::testing::{pending_with, run, Dropped};
+    use super::{join, try_join};
+    use crate::runtime::yield_now;
+
+    #[test]
+    fn join_waits_for_both() {
+        let output = run(join(ready(1), async {
+            yield_now().await;
+            yield_now().await;
+            "two"
+        }));
+        assert_eq!(output, (1, "two"));
+    }
+
+    #[test]
+    fn try_join_short_circuits_on_the_first_error() {
+        let (guard, dropped) = Dropped::new();
+        let output = run(try_join(pending_with::<Result<u32, &str>>(guard), async {
+            yield_now().await;
+            Err::<u32, _>("failed")
+        }));
+
+        // The other future is dropped right away
+        assert_eq!(output, Err("failed"));
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let output = run(try_join(ready(Ok::<_, &str>(1)), async {
+            yield_now().await;
+            Ok(2)
+        }));
+        assert_eq!(output, Ok((1, 2)));
+    }
+
+    #[test]
+    fn join_macro_waits_for_all() {
+        let output = run(async {
+            crate::join!(
+                ready(1),
+                async {
+                    yield_now().await;
+                    2
+                },
+                ready("three")
+            )
+        });
+        assert_eq!(output, (1, 2, "three"));
+    }
+
+    #[test]
+    fn try_join_macro_short_circuits_on_the_first_error() {
+        let (guard, dropped) = Dropped::new();
+        let output: Result<(u32, u32, u32), &str> = run(async {
+            crate::try_join!(
+                pending_with(guard),
+                async {
+                    yield_now().await;
+                    Err("second")
+                },
+                async {
+                    yield_now().await;
+                    yield_now().await;
+                    Err("third")
+                },
+            )
+        });
+        assert_eq!(output, Err("second"));
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let output: Result<_, &str> = run(async {
+            crate::try_join!(ready(Ok(1)), async {
+                yield_now().await;
+                Ok("two")
+            })
+        });
+        assert_eq!(output, Ok((1, "two")));
+    }
+}
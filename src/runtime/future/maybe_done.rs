@@ -0,0 +1,58 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// Enum representing a future polled by a combinator, which keeps its output
+// once it completed until the combinator takes it. Used by join!, try_join!
+// and select!, for every branch.
+pub enum MaybeDone<F: Future> {
+    Future(Pin<Box<F>>), // Still running
+    Done(F::Output),     // Completed, output not taken yet
+    Gone,                // Output taken, or the future cancelled
+}
+
+impl<F: Future> Unpin for MaybeDone<F> {}
+
+impl<F: Future> MaybeDone<F> {
+    pub fn new(future: F) -> Self {
+        MaybeDone::Future(Box::pin(future))
+    }
+
+    // Function to poll the future if it is still running. Ready once it is not.
+    pub fn poll_done(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let MaybeDone::Future(future) = self {
+            match future.as_mut().poll(cx) {
+                Poll::Ready(output) => *self = MaybeDone::Done(output),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self, MaybeDone::Future(_))
+    }
+
+    // Get the output of the future, if it completed and was not taken yet
+    pub fn output(&self) -> Option<&F::Output> {
+        match self {
+            MaybeDone::Done(output) => Some(output),
+            _ => None,
+        }
+    }
+
+    pub fn take_output(&mut self) -> Option<F::Output> {
+        match std::mem::replace(self, MaybeDone::Gone) {
+            MaybeDone::Done(output) => Some(output),
+            other => {
+                *self = other;
+                None
+            }
+        }
+    }
+
+    // Function to drop the future, cancelling what it was waiting for, or its output
+    pub fn cancel(&mut self) {
+        *self = MaybeDone::Gone;
+    }
+}
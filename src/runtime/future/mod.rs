@@ -0,0 +1,64 @@
+pub mod join;
+pub mod maybe_done;
+pub mod select;
+
+pub use join::{join, try_join, Join, TryJoin};
+pub use maybe_done::MaybeDone;
+pub use select::{select, select_biased, Either, Select};
+
+// Count the tokens given, used by the macros to number their branches
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __count {
+    () => {
+        0usize
+    };
+    ($head:tt $($tail:tt)*) => {
+        1usize + $crate::__count!($($tail)*)
+    };
+}
+
+// Helpers driving futures by hand in the tests of the combinators
+#[cfg(test)]
+mod testing {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Waker};
+
+    // Poll a future until it completes. Every future of the tests either
+    // completes or yields, so no waker is needed.
+    pub fn run<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        for _ in 0..1000 {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+        panic!("the future did not complete");
+    }
+
+    // Value recording that it was dropped, to tell a future was cancelled
+    pub struct Dropped(Arc<AtomicBool>);
+
+    impl Dropped {
+        pub fn new() -> (Dropped, Arc<AtomicBool>) {
+            let flag = Arc::new(AtomicBool::new(false));
+            (Dropped(flag.clone()), flag)
+        }
+    }
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // Future never completing, dropping the value once cancelled
+    pub async fn pending_with<T>(guard: Dropped) -> T {
+        let _guard = guard;
+        std::future::pending().await
+    }
+}
@@ -0,0 +1,271 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::runtime::rand;
+
+// Output of a select of two futures: the one that completed first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+// Function to wait for the first of two futures to complete. The other one is
+// dropped, cancelling what it was waiting for. Which is polled first is picked
+// at random on every poll, so a future always ready cannot starve the other.
+pub fn select<A: Future, B: Future>(a: A, b: B) -> Select<A, B> {
+    Select {
+        a: Some(Box::pin(a)),
+        b: Some(Box::pin(b)),
+        biased: false,
+    }
+}
+
+// Function to wait for the first of two futures to complete, always polling
+// the first one first, e.g. to check a shutdown before serving more work
+pub fn select_biased<A: Future, B: Future>(a: A, b: B) -> Select<A, B> {
+    Select {
+        a: Some(Box::pin(a)),
+        b: Some(Box::pin(b)),
+        biased: true,
+    }
+}
+
+// Future for racing two futures
+pub struct Select<A, B> {
+    a: Option<Pin<Box<A>>>, // Dropped once either future completed
+    b: Option<Pin<Box<B>>>,
+    biased: bool, // Poll a first every time instead of a random one
+}
+
+impl<A: Future, B: Future> Select<A, B> {
+    fn poll_a(&mut self, cx: &mut Context<'_>) -> Poll<Either<A::Output, B::Output>> {
+        let a = self.a.as_mut().expect("Select polled after completion");
+        a.as_mut().poll(cx).map(Either::Left)
+    }
+
+    fn poll_b(&mut self, cx: &mut Context<'_>) -> Poll<Either<A::Output, B::Output>> {
+        let b = self.b.as_mut().expect("Select polled after completion");
+        b.as_mut().poll(cx).map(Either::Right)
+    }
+}
+
+impl<A: Future, B: Future> Future for Select<A, B> {
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        let output = if state.biased || rand::below(2) == 0 {
+            match state.poll_a(cx) {
+                Poll::Pending => state.poll_b(cx),
+                ready => ready,
+            }
+        } else {
+            match state.poll_b(cx) {
+                Poll::Pending => state.poll_a(cx),
+                ready => ready,
+            }
+        };
+
+        // Cancel the loser right away rather than when the Select is dropped
+        if output.is_ready() {
+            state.a = None;
+            state.b = None;
+        }
+        output
+    }
+}
+
+// Macro waiting, from an async context, for the first of any number of
+// futures whose output matches the pattern of its branch, then evaluating the
+// handler of that branch. The other futures are dropped before the handler
+// runs, cancelling what they were waiting for, so it can reuse what they
+// borrowed. A branch whose output does not match is disabled, and select!
+// panics if all are. Branches are polled from a random one on every poll,
+// unless "biased;" comes first, which polls them in order:
+//
+//     select! {
+//         biased;
+//         _ = shutdown.cancelled() => return Ok(()),
+//         read = stream.read(&mut buf) => read?,
+//         _ = time::sleep(CLIENT_TIMEOUT) => return Err(IOError::TimedOut),
+//     }
+#[macro_export]
+macro_rules! select {
+    // Pair every branch with the tuple fields before its own, "_" each
+    (@ { $biased:tt ( $($count:tt)* ) $( ( $($skip:tt)* ) $p:pat = $e:expr => $h:expr, )* }
+        $pn:pat = $en:expr => $hn:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@ {
+            $biased ( $($count)* _ ) $( ( $($skip)* ) $p = $e => $h, )* ( $($count)* ) $pn = $en => $hn,
+        } $($($rest)*)?)
+    };
+
+    (@ { $biased:tt ( $($count:tt)* ) $( ( $($skip:tt)* ) $p:pat = $e:expr => $h:expr, )* }) => {{
+        const BRANCHES: usize = $crate::__count!($($count)*);
+        let mut futures = ( $( $crate::runtime::future::MaybeDone::new($e), )* );
+
+        std::future::poll_fn(|cx| {
+            let start = if $biased { 0 } else { $crate::runtime::rand::below(BRANCHES) };
+            let mut running = false;
+
+            for i in 0..BRANCHES {
+                let branch = (start + i) % BRANCHES;
+                $(
+                    let ( $($skip,)* future, .. ) = &mut futures;
+                    if branch == $crate::__count!($($skip)*) && future.is_running() {
+                        if future.poll_done(cx).is_ready() {
+                            #[allow(unused_variables, unreachable_patterns, clippy::redundant_pattern_matching)]
+                            let matched = matches!(future.output(), Some($p));
+                            if matched {
+                                return std::task::Poll::Ready(());
+                            }
+                            future.cancel();
+                        } else {
+                            running = true;
+                        }
+                    }
+                )*
+            }
+
+            if running {
+                std::task::Poll::Pending
+            } else {
+                panic!("all branches of select! are disabled")
+            }
+        })
+        .await;
+
+        // Take the output of the winner, then cancel the others
+        let mut outputs = ( $({
+            let ( $($skip,)* future, .. ) = &mut futures;
+            future.take_output()
+        }, )* );
+        drop(futures);
+
+        $(
+            if let Some($p) = {
+                let ( $($skip,)* output, .. ) = &mut outputs;
+                output.take()
+            } {
+                $h
+            } else
+        )* {
+            unreachable!("select! completed without a winning branch")
+        }
+    }};
+
+    (biased; $($branches:tt)*) => {
+        $crate::select!(@ { true () } $($branches)*)
+    };
+
+    ($($branches:tt)*) => {
+        $crate::select!(@ { false () } $($branches)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::ready;
+    use std::sync::atomic::Ordering;
+
+    use super::super::testing::{pending_with, run, Dropped};
+    use super::{select, select_biased, Either};
+    use crate::runtime::yield_now;
+
+    #[test]
+    fn select_completes_with_the_first_and_drops_the_other() {
+        let (guard, dropped) = Dropped::new();
+        let mut select = Box::pin(select(pending_with::<u32>(guard), async {
+            yield_now().await;
+            "right"
+        }));
+
+        // The loser is dropped as soon as the winner completes
+        assert_eq!(run(select.as_mut()), Either::Right("right"));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn select_biased_polls_the_first_first() {
+        for _ in 0..100 {
+            assert_eq!(run(select_biased(ready(1), ready(2))), Either::Left(1));
+        }
+    }
+
+    #[test]
+    fn select_picks_a_random_first() {
+        let lefts = (0..200)
+            .filter(|_| run(select(ready(1), ready(2))) == Either::Left(1))
+            .count();
+        assert!(lefts > 0 && lefts < 200, "{lefts} of 200 were Left");
+    }
+
+    #[test]
+    fn select_macro_disables_unmatched_branches() {
+        let output = run(async {
+            crate::select! {
+                biased;
+                Some(value) = ready(None::<u32>) => value,
+                value = async { yield_now().await; 2 } => value,
+            }
+        });
+        assert_eq!(output, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "all branches of select! are disabled")]
+    fn select_macro_panics_once_every_branch_is_disabled() {
+        run(async {
+            crate::select! {
+                Some(value) = ready(None::<u32>) => value,
+                Ok(value) = ready(Err::<u32, ()>(())) => value,
+            }
+        });
+    }
+
+    #[test]
+    fn select_macro_drops_the_others_before_the_handler() {
+        let (guard, dropped) = Dropped::new();
+        let output = run(async {
+            crate::select! {
+                _ = pending_with::<()>(guard) => unreachable!(),
+                value = ready(1) => {
+                    assert!(dropped.load(Ordering::SeqCst));
+                    value
+                }
+            }
+        });
+        assert_eq!(output, 1);
+    }
+
+    #[test]
+    fn select_macro_biased_polls_in_order() {
+        for _ in 0..100 {
+            let output = run(async {
+                crate::select! {
+                    biased;
+                    value = ready(1) => value,
+                    value = ready(2) => value,
+                    value = ready(3) => value,
+                }
+            });
+            assert_eq!(output, 1);
+        }
+
+        // Without biased, every branch gets to win
+        let mut wins = [0; 3];
+        for _ in 0..300 {
+            let output: usize = run(async {
+                crate::select! {
+                    value = ready(0) => value,
+                    value = ready(1) => value,
+                    value = ready(2) => value,
+                }
+            });
+            wins[output] += 1;
+        }
+        assert!(wins.iter().all(|&won| won > 0), "wins: {wins:?}");
+    }
+}
@@ -0,0 +1,221 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::priority::Priority;
+use super::task::{Schedule, Task};
+use crate::core::error::JoinError;
+
+// Future of a local task, polled only on the thread that spawned it
+pub type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+// State shared between a spawned task and its JoinHandle
+struct JoinState<T> {
+    output: Option<std::result::Result<T, JoinError>>, // Output of the task, once available
+    waker: Option<Waker>,                              // Waker of the task awaiting the JoinHandle
+    aborted: bool,                                     // Set when abort() was requested
+    finished: bool,                                    // Set once the task has produced its output
+}
+
+impl<T> JoinState<T> {
+    // Store the task output and wake up whoever is awaiting it
+    fn complete(&mut self, output: std::result::Result<T, JoinError>) {
+        self.output = Some(output);
+        self.finished = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// Handle to a spawned task that can be awaited for the task's output
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    waker: Option<Waker>, // Wakes the task so it sees an abort, None for closures on the blocking pool
+}
+
+impl<T> JoinHandle<T> {
+    // Cancel the task. The task's future is dropped the next time the executor
+    // polls it and awaiting the handle resolves to JoinError::Cancelled. A
+    // blocking closure can only be cancelled before it starts running.
+    pub fn abort(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.finished || state.aborted {
+                return;
+            }
+            state.aborted = true;
+        }
+
+        // Schedule the task so the executor drops its future
+        if let Some(waker) = &self.waker {
+            waker.wake_by_ref();
+        }
+    }
+
+    // Check whether the task has finished, either normally, by panic or by abort
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = std::result::Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None if state.finished => panic!("JoinHandle polled after completion"),
+            None => {
+                // Remember the waker so the task can notify us once it completes
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Future wrapping a spawned future, forwarding its output to the JoinHandle
+struct Harness<F: Future> {
+    future: Pin<Box<F>>,
+    state: Arc<Mutex<JoinState<F::Output>>>,
+}
+
+impl<F: Future> Future for Harness<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.aborted {
+                state.complete(Err(JoinError::Cancelled));
+                return Poll::Ready(());
+            }
+        }
+
+        // Catch panics so a misbehaving task does not take the executor down
+        let future = self.future.as_mut();
+        let output = match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(output)) => Ok(output),
+            Err(payload) => Err(JoinError::Panicked(panic_message(payload))),
+        };
+
+        self.state.lock().unwrap().complete(output);
+        Poll::Ready(())
+    }
+}
+
+// A harness dropped before completing, e.g. by RemainingTasks::Cancel, cancels the task
+impl<F: Future> Drop for Harness<F> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.finished {
+            state.complete(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+// Extract a printable message from a panic payload
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+// Create the state shared between a task and its JoinHandle
+fn join_state<T>() -> Arc<Mutex<JoinState<T>>> {
+    Arc::new(Mutex::new(JoinState {
+        output: None,
+        waker: None,
+        aborted: false,
+        finished: false,
+    }))
+}
+
+// Wrap a future into a Task and return it together with its JoinHandle
+pub fn joinable<F>(
+    future: F,
+    scheduler: Arc<dyn Schedule>,
+    priority: Priority,
+) -> (Arc<Task>, JoinHandle<F::Output>)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = join_state();
+
+    let harness = Harness {
+        future: Box::pin(future),
+        state: state.clone(),
+    };
+    let task = Task::new(Box::pin(harness), scheduler, priority);
+
+    let waker = task.waker();
+    (
+        task,
+        JoinHandle {
+            state,
+            waker: Some(waker),
+        },
+    )
+}
+
+// Wrap a future that may not be Send for a local task, returning it together
+// with its JoinHandle. The waker is the one of the local task.
+pub fn local<F>(future: F, waker: Waker) -> (LocalFuture, JoinHandle<F::Output>)
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let state = join_state();
+
+    let harness = Harness {
+        future: Box::pin(future),
+        state: state.clone(),
+    };
+
+    (
+        Box::pin(harness),
+        JoinHandle {
+            state,
+            waker: Some(waker),
+        },
+    )
+}
+
+// Wrap a blocking closure into a job for the blocking pool and return it
+// together with its JoinHandle. Completing the job wakes the awaiting task.
+pub fn blocking<F, R>(f: F) -> (Box<dyn FnOnce() + Send>, JoinHandle<R>)
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let state = join_state();
+    let job_state = state.clone();
+
+    let job = Box::new(move || {
+        {
+            let mut state = job_state.lock().unwrap();
+            if state.aborted {
+                state.complete(Err(JoinError::Cancelled));
+                return;
+            }
+        }
+
+        let output = panic::catch_unwind(AssertUnwindSafe(f))
+            .map_err(|payload| JoinError::Panicked(panic_message(payload)));
+        job_state.lock().unwrap().complete(output);
+    });
+
+    (job, JoinHandle { state, waker: None })
+}
@@ -0,0 +1,218 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Wake, Waker};
+use std::thread::{self, ThreadId};
+
+use super::coop;
+use super::executor::EXECUTOR;
+use super::join_handle::{self, JoinHandle, LocalFuture};
+use super::reactor::{Reactor, REACTOR};
+use crate::core::result::Result;
+
+// Define a thread-local variable holding the LocalSet being run on the current
+// thread, where spawn_local puts its tasks
+thread_local! {
+    static CURRENT: RefCell<Option<LocalSet>> = const { RefCell::new(None) };
+}
+
+// Struct representing a set of tasks that may not be Send, polled only on the
+// thread that created it. The set is run by block_on, alongside the tasks of
+// the current-thread executor. Cloning it gives another handle to the same set.
+#[derive(Clone)]
+pub struct LocalSet {
+    shared: Rc<Shared>,
+}
+
+struct Shared {
+    tasks: RefCell<HashMap<usize, LocalTask>>, // Tasks not completed yet, by id
+    next_id: Cell<usize>,
+    queue: Arc<ReadyQueue>, // Tasks woken, possibly from other threads
+}
+
+struct LocalTask {
+    future: LocalFuture,
+    waker: Arc<TaskWaker>,
+}
+
+// Ids of the woken tasks. This part of the set is shared with the wakers, which
+// may be called from any thread.
+struct ReadyQueue {
+    ready: Mutex<VecDeque<usize>>,
+    reactor: Arc<Reactor>, // Reactor of the thread running the set
+    owner: ThreadId,       // Thread running the set
+}
+
+// Waker of a local task: queues its id and interrupts the reactor wait of the
+// thread running the set if woken from elsewhere
+struct TaskWaker {
+    id: usize,
+    queue: Arc<ReadyQueue>,
+    scheduled: AtomicBool, // Set while the id sits in the ready queue
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        self.queue.ready.lock().unwrap().push_back(self.id);
+        if thread::current().id() != self.queue.owner {
+            self.queue.reactor.unpark();
+        }
+    }
+}
+
+impl LocalSet {
+    pub fn new() -> Self {
+        LocalSet {
+            shared: Rc::new(Shared {
+                tasks: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                queue: Arc::new(ReadyQueue {
+                    ready: Mutex::new(VecDeque::new()),
+                    reactor: REACTOR.with(|current| current.borrow().clone()),
+                    owner: thread::current().id(),
+                }),
+            }),
+        }
+    }
+
+    // Function to spawn a future onto the set, returning a handle to await its
+    // output. It runs the next time the set is run.
+    pub fn spawn_local<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let id = self.shared.next_id.get();
+        self.shared.next_id.set(id + 1);
+
+        let waker = Arc::new(TaskWaker {
+            id,
+            queue: self.shared.queue.clone(),
+            scheduled: AtomicBool::new(false),
+        });
+        let (future, handle) = join_handle::local(f, Waker::from(waker.clone()));
+
+        self.shared.tasks.borrow_mut().insert(
+            id,
+            LocalTask {
+                future,
+                waker: waker.clone(),
+            },
+        );
+        waker.wake_by_ref();
+        handle
+    }
+
+    // Function to block the current thread and run the future to completion on
+    // it, together with the tasks of the set and of the current-thread
+    // executor. The future may spawn tasks that are not Send with spawn_local.
+    pub fn block_on<F>(&self, f: F) -> Result<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let root = self.spawn_local(f);
+        EXECUTOR.with(|executor| executor.borrow().run_until(root, self))
+    }
+
+    // Function to make spawn_local on the current thread use this set, until
+    // the returned guard is dropped
+    pub fn enter(&self) -> Entered {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        Entered { previous }
+    }
+
+    // Function to poll every task woken so far. Tasks woken meanwhile, e.g. by
+    // yield_now, wait for the next call. Returns the number of tasks polled.
+    pub fn run_ready(&self) -> usize {
+        let ready = mem::take(&mut *self.shared.queue.ready.lock().unwrap());
+        let mut polled = 0;
+
+        for id in ready {
+            // Take the task out of the set while it runs, it may spawn others
+            let Some(mut task) = self.shared.tasks.borrow_mut().remove(&id) else {
+                continue; // Woken after completion
+            };
+            polled += 1;
+
+            // Wakeups from now on must queue the task again
+            task.waker.scheduled.store(false, Ordering::Release);
+
+            let waker = Waker::from(task.waker.clone());
+            let mut cx = Context::from_waker(&waker);
+            if coop::budget(|| task.future.as_mut().poll(&mut cx)).is_pending() {
+                self.shared.tasks.borrow_mut().insert(id, task);
+            }
+        }
+
+        polled
+    }
+
+    // Get the number of tasks not completed yet
+    pub fn len(&self) -> usize {
+        self.shared.tasks.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Function to drop every task of the set, resolving their JoinHandles to Cancelled
+    pub fn cancel_all(&self) {
+        let tasks: Vec<LocalTask> = self
+            .shared
+            .tasks
+            .borrow_mut()
+            .drain()
+            .map(|(_, task)| task)
+            .collect();
+        drop(tasks); // Dropped outside the borrow, they may wake other tasks
+    }
+}
+
+impl Default for LocalSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Guard restoring the LocalSet spawn_local used before LocalSet::enter
+pub struct Entered {
+    previous: Option<LocalSet>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+// Function to spawn a future that may not be Send onto the LocalSet run by the
+// current thread, or onto the simulation running on it. Panics outside of
+// LocalSet::block_on, executor::block_on and a simulation.
+pub fn spawn_local<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    if super::sim::is_current() {
+        return super::sim::spawn(f);
+    }
+
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(set) => set.spawn_local(f),
+        None => panic!("spawn_local called outside of a LocalSet or the current-thread executor"),
+    })
+}
@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::priority::Priority;
+
+// Upper bounds of the buckets of a poll time histogram, the last bucket holds
+// every poll from the last bound on
+pub const POLL_TIME_BOUNDS: [Duration; 5] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+];
+
+// Polls taking at least this long are slow: the task kept its worker from
+// running the others, typically by blocking on I/O or a lock
+pub const SLOW_POLL: Duration = Duration::from_millis(1);
+
+// Snapshot of the activity of a runtime, from Runtime::metrics
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeMetrics {
+    pub tasks_spawned: u64,           // Tasks spawned onto the runtime so far
+    pub tasks_completed: u64,         // Tasks that ran to completion so far
+    pub tasks_alive: usize,           // Tasks spawned and not completed or cancelled yet
+    pub injector_depth: usize,        // Tasks waiting in the global run queue
+    pub workers: Vec<WorkerMetrics>,  // Activity of every worker, by index
+    pub reactor: ReactorMetrics,      // Activity of the reactor shared by the workers
+    pub poll_times: PollHistogram,    // Duration of every task poll so far
+    pub slow_tasks: Vec<TaskMetrics>, // Live tasks that had at least one slow poll
+}
+
+impl RuntimeMetrics {
+    // Number of task polls over every worker
+    pub fn polls(&self) -> u64 {
+        self.workers.iter().map(|worker| worker.polls).sum()
+    }
+
+    // Number of tasks waiting in every run queue, the injector included
+    pub fn queue_depth(&self) -> usize {
+        self.injector_depth
+            + self
+                .workers
+                .iter()
+                .map(|worker| worker.queue_depth)
+                .sum::<usize>()
+    }
+
+    // Time spent polling tasks, over every worker
+    pub fn busy(&self) -> Duration {
+        self.workers.iter().map(|worker| worker.busy).sum()
+    }
+
+    // Time spent idle, waiting on the reactor or for work, over every worker
+    pub fn parked(&self) -> Duration {
+        self.workers.iter().map(|worker| worker.parked).sum()
+    }
+}
+
+// Snapshot of the activity of a worker thread
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkerMetrics {
+    pub polls: u64,         // Task polls run by the worker
+    pub busy: Duration,     // Time spent polling tasks
+    pub parked: Duration,   // Time spent idle, waiting on the reactor or for work
+    pub queue_depth: usize, // Tasks waiting in the local run queue right now
+}
+
+// Snapshot of the activity of a reactor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReactorMetrics {
+    pub waits: u64,        // Waits on the poller, blocking or not
+    pub events: u64,       // I/O events and completions reported by those waits
+    pub registered: usize, // File descriptors registered right now
+}
+
+impl ReactorMetrics {
+    // Average number of events a wait reported
+    pub fn events_per_wait(&self) -> f64 {
+        if self.waits == 0 {
+            return 0.0;
+        }
+        self.events as f64 / self.waits as f64
+    }
+}
+
+// Snapshot of the poll times of a live task
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskMetrics {
+    pub id: usize, // Identity of the task, stable while it is alive
+    pub priority: Priority,
+    pub poll_times: PollHistogram,
+}
+
+// Number of polls whose duration fell in every bucket of POLL_TIME_BOUNDS
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PollHistogram {
+    pub buckets: [u64; POLL_TIME_BOUNDS.len() + 1],
+}
+
+impl PollHistogram {
+    // Number of polls counted
+    pub fn polls(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    // Number of polls that took at least SLOW_POLL
+    pub fn slow(&self) -> u64 {
+        let first = POLL_TIME_BOUNDS
+            .iter()
+            .position(|bound| *bound > SLOW_POLL)
+            .unwrap_or(POLL_TIME_BOUNDS.len());
+        self.buckets[first..].iter().sum()
+    }
+}
+
+// Counters behind a PollHistogram, updated on every poll
+#[derive(Default)]
+pub struct PollTimes {
+    buckets: [AtomicU64; POLL_TIME_BOUNDS.len() + 1],
+}
+
+impl PollTimes {
+    // Record a poll that took `elapsed`
+    pub fn record(&self, elapsed: Duration) {
+        let bucket = POLL_TIME_BOUNDS
+            .iter()
+            .position(|bound| elapsed < *bound)
+            .unwrap_or(POLL_TIME_BOUNDS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> PollHistogram {
+        PollHistogram {
+            buckets: self
+                .buckets
+                .each_ref()
+                .map(|count| count.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+// Counters of the activity of a worker thread, updated by the worker
+pub struct WorkerStats {
+    epoch: Instant, // Origin of parked_since
+    polls: AtomicU64,
+    busy_nanos: AtomicU64,
+    parked_nanos: AtomicU64, // Time spent in the parks that ended
+    parked_since: AtomicU64, // Nanoseconds from the epoch to the start of the current park, 0 if none
+}
+
+impl WorkerStats {
+    pub fn new() -> Self {
+        WorkerStats {
+            epoch: Instant::now(),
+            polls: AtomicU64::new(0),
+            busy_nanos: AtomicU64::new(0),
+            parked_nanos: AtomicU64::new(0),
+            parked_since: AtomicU64::new(0),
+        }
+    }
+
+    // Record a task poll which took `elapsed`
+    pub fn polled(&self, elapsed: Duration) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.busy_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Record the worker starting to wait on the reactor or for work
+    pub fn park(&self) {
+        let since = self.epoch.elapsed().as_nanos() as u64;
+        self.parked_since.store(since.max(1), Ordering::Relaxed);
+    }
+
+    // Record the worker done waiting
+    pub fn unpark(&self) {
+        let since = self.parked_since.swap(0, Ordering::Relaxed);
+        if since > 0 {
+            let now = self.epoch.elapsed().as_nanos() as u64;
+            self.parked_nanos
+                .fetch_add(now.saturating_sub(since), Ordering::Relaxed);
+        }
+    }
+
+    // Get a snapshot of the counters, with the depth of the local run queue.
+    // The parked time includes the park in progress, if any.
+    pub fn snapshot(&self, queue_depth: usize) -> WorkerMetrics {
+        let mut parked = self.parked_nanos.load(Ordering::Relaxed);
+        let since = self.parked_since.load(Ordering::Relaxed);
+        if since > 0 {
+            parked += (self.epoch.elapsed().as_nanos() as u64).saturating_sub(since);
+        }
+
+        WorkerMetrics {
+            polls: self.polls.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+            parked: Duration::from_nanos(parked),
+            queue_depth,
+        }
+    }
+}
+
+impl Default for WorkerStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
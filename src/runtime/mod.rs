@@ -1,9 +1,44 @@
+pub mod blocking;
+pub mod builder;
+pub mod coop;
 pub mod executor;
+pub mod future;
+pub mod join_handle;
+pub mod local;
+pub mod metrics;
+pub mod multi_thread;
 pub mod net;
 pub mod polling;
+pub mod priority;
+pub mod rand;
 pub mod reactor;
+pub mod ready;
+#[allow(clippy::module_inception)]
+pub mod runtime;
+pub mod scope;
+pub mod shutdown;
+pub mod signal;
+pub mod sim;
+pub mod sync;
 pub mod task;
+pub mod task_local;
 pub mod task_queue;
+pub mod threads;
+pub mod time;
 
+pub use builder::Builder;
+pub use coop::yield_now;
+pub use join_handle::JoinHandle;
+pub use local::LocalSet;
+pub use metrics::RuntimeMetrics;
+pub use multi_thread::MultiThread;
 pub use net::tcp_listener::TcpListener;
-pub use net::tcp_stream::TcpStream;
+pub use net::tcp_stream::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, TcpStream, WriteHalf};
+pub use polling::Backend;
+pub use priority::{ClassMetrics, Priority};
+pub use ready::Ready;
+pub use runtime::Runtime;
+pub use scope::{scope, Scope};
+pub use shutdown::{CancellationToken, ShutdownReport};
+pub use task::RemainingTasks;
+pub use task_local::TaskLocal;
@@ -0,0 +1,486 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use super::blocking::BlockingPool;
+use super::coop;
+use super::executor;
+use super::join_handle::{self, JoinHandle};
+use super::metrics::{PollTimes, RuntimeMetrics, TaskMetrics, WorkerStats};
+use super::priority::{ClassMetrics, Priority, PriorityStats};
+use super::reactor::{Reactor, REACTOR};
+use super::task::{OwnedTasks, Schedule, Task};
+use super::task_queue::TaskQueue;
+use super::threads::ThreadConfig;
+use crate::core::{error::IOError, result::Result};
+
+// Number of local polls after which a worker checks the global injector first,
+// so tasks scheduled from outside the pool are not starved by busy local queues
+const GLOBAL_QUEUE_INTERVAL: u32 = 61;
+
+// Default number of tasks a worker queues locally before overflowing to the injector
+pub const DEFAULT_LOCAL_QUEUE_CAPACITY: usize = 256;
+
+// Define a thread-local variable holding the pool identity and worker index of the current thread
+thread_local! {
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+// Struct representing a work-stealing executor running tasks on several worker threads
+pub struct MultiThread {
+    shared: Arc<Shared>,
+}
+
+// Settings of a pool, see runtime::Builder
+pub struct Config {
+    pub workers: usize,              // Number of worker threads
+    pub local_queue_capacity: usize, // Tasks a worker queues locally before using the injector
+    pub threads: ThreadConfig,       // Name and hooks of the worker threads
+}
+
+impl Config {
+    // Constructor to create the default settings of a pool with the given number of workers
+    pub fn new(workers: usize) -> Self {
+        Config {
+            workers,
+            local_queue_capacity: DEFAULT_LOCAL_QUEUE_CAPACITY,
+            threads: ThreadConfig::default(),
+        }
+    }
+}
+
+// State shared between the workers of a pool
+struct Shared {
+    injector: TaskQueue, // Global queue for tasks scheduled from outside the pool, or not Normal
+    locals: Vec<Mutex<VecDeque<Arc<Task>>>>, // Run queue of every worker
+    sleepers: Mutex<usize>, // Number of workers parked on the condvar
+    condvar: Condvar,    // Used to unpark idle workers when work shows up
+    driving: AtomicBool, // Set while a worker is blocked waiting on the reactor
+    reactor: Arc<Reactor>, // Reactor shared by all workers
+    blocking: Arc<BlockingPool>, // Threads running the closures of spawn_blocking
+    local_capacity: usize, // Tasks a worker queues locally before using the injector
+    shutdown: AtomicBool, // Set once the workers have to exit
+    threads: ThreadConfig, // Name and hooks of the worker threads
+    owned: OwnedTasks,   // Tasks spawned onto the pool and not completed yet
+    stats: PriorityStats, // Scheduling activity of every class
+    workers: Vec<WorkerStats>, // Activity of every worker
+    poll_times: PollTimes, // Duration of every task poll
+}
+
+impl MultiThread {
+    // Constructor to create a pool with the given number of worker threads
+    pub fn new(workers: usize) -> Self {
+        Self::with_config(
+            Config::new(workers),
+            Reactor::new(),
+            BlockingPool::default(),
+        )
+    }
+
+    // Constructor to create a pool from the given settings, whose workers share
+    // the given reactor and blocking pool
+    pub fn with_config(config: Config, reactor: Reactor, blocking: BlockingPool) -> Self {
+        assert!(
+            config.workers > 0,
+            "a multi-threaded executor needs at least one worker"
+        );
+
+        MultiThread {
+            shared: Arc::new(Shared {
+                injector: TaskQueue::new(),
+                locals: (0..config.workers)
+                    .map(|_| Mutex::new(VecDeque::new()))
+                    .collect(),
+                sleepers: Mutex::new(0),
+                condvar: Condvar::new(),
+                driving: AtomicBool::new(false),
+                reactor: Arc::new(reactor),
+                blocking: Arc::new(blocking),
+                local_capacity: config.local_queue_capacity,
+                shutdown: AtomicBool::new(false),
+                threads: config.threads,
+                owned: OwnedTasks::default(),
+                stats: PriorityStats::default(),
+                workers: (0..config.workers)
+                    .map(|_| WorkerStats::default())
+                    .collect(),
+                poll_times: PollTimes::default(),
+            }),
+        }
+    }
+
+    // Function to spawn a Future onto the pool
+    pub fn spawn<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_with_priority(Priority::Normal, f)
+    }
+
+    // Function to spawn a Future onto the pool in the given scheduling class
+    pub fn spawn_with_priority<F>(&self, priority: Priority, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (task, handle) = join_handle::joinable(f, self.shared.clone(), priority);
+        task.schedule();
+        handle
+    }
+
+    // Function to start the worker threads. `on_exit` is called with the result
+    // of every worker when it exits.
+    pub fn start(&self, on_exit: Arc<dyn Fn(Result<()>) + Send + Sync>) -> Result<()> {
+        for index in 0..self.shared.locals.len() {
+            let worker = Worker {
+                shared: self.shared.clone(),
+                index,
+            };
+            let on_exit = on_exit.clone();
+
+            self.shared
+                .threads
+                .spawn(&format!("worker-{index}"), move || on_exit(worker.run()))
+                .map_err(|err| IOError::SyscallResult(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    // Number of worker threads of the pool
+    pub fn workers(&self) -> usize {
+        self.shared.locals.len()
+    }
+
+    // Tasks spawned onto the pool and not completed yet
+    pub fn tasks(&self) -> &OwnedTasks {
+        &self.shared.owned
+    }
+
+    // Get the scheduling activity of a class, over every worker
+    pub fn class_metrics(&self, priority: Priority) -> ClassMetrics {
+        let shared = &self.shared;
+
+        // Tasks of the class waiting in the injector and in the local queues
+        let queued = shared.injector.len_of(priority)
+            + shared
+                .locals
+                .iter()
+                .map(|local| {
+                    let local = local.lock().unwrap();
+                    local
+                        .iter()
+                        .filter(|task| task.priority == priority)
+                        .count()
+                })
+                .sum::<usize>();
+
+        shared.stats.snapshot(priority, queued)
+    }
+
+    // Get a snapshot of the activity of the pool: tasks, run queues, workers and reactor
+    pub fn metrics(&self) -> RuntimeMetrics {
+        let shared = &self.shared;
+
+        RuntimeMetrics {
+            tasks_spawned: shared.owned.spawned() as u64,
+            tasks_completed: shared.owned.completed() as u64,
+            tasks_alive: shared.owned.len(),
+            injector_depth: shared.injector.len(),
+            workers: shared
+                .workers
+                .iter()
+                .zip(&shared.locals)
+                .map(|(stats, local)| stats.snapshot(local.lock().unwrap().len()))
+                .collect(),
+            reactor: shared.reactor.metrics(),
+            poll_times: shared.poll_times.snapshot(),
+            slow_tasks: shared
+                .owned
+                .live()
+                .iter()
+                .map(|task| TaskMetrics {
+                    id: task.id(),
+                    priority: task.priority,
+                    poll_times: task.poll_times.snapshot(),
+                })
+                .filter(|task| task.poll_times.slow() > 0)
+                .collect(),
+        }
+    }
+
+    // Function to make the workers exit once they are done with the task they are
+    // running. Tasks left in the run queues are not polled again.
+    pub fn shutdown(&self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+
+        // Wake the parked workers and the one blocked on the reactor
+        let _sleepers = self.shared.sleepers.lock().unwrap();
+        self.shared.condvar.notify_all();
+        self.shared.reactor.unpark();
+    }
+}
+
+impl Shared {
+    // Identity of the pool, used to tell whether the current thread belongs to it
+    fn id(&self) -> usize {
+        self as *const Shared as usize
+    }
+
+    // Check whether any run queue holds a task
+    fn has_work(&self) -> bool {
+        !self.injector.is_empty()
+            || self
+                .locals
+                .iter()
+                .any(|local| !local.lock().unwrap().is_empty())
+    }
+
+    // Unpark one idle worker, if any. Returns whether one was notified.
+    fn notify_one(&self) -> bool {
+        let sleepers = self.sleepers.lock().unwrap();
+        if *sleepers > 0 {
+            self.condvar.notify_one();
+        }
+        *sleepers > 0
+    }
+}
+
+// Normal tasks are pushed onto the local queue of the waking worker, or the
+// injector otherwise. The other classes always go through the injector, which
+// serves them by weight.
+impl Schedule for Shared {
+    fn bind(&self, task: &Arc<Task>) {
+        self.owned.insert(task);
+    }
+
+    fn release(&self, task: &Arc<Task>) {
+        self.owned.remove(task);
+    }
+
+    fn schedule(&self, task: Arc<Task>) {
+        self.stats.scheduled(task.priority);
+
+        match WORKER.with(|worker| worker.get()) {
+            Some((id, index)) if id == self.id() => {
+                let mut local = self.locals[index].lock().unwrap();
+                if task.priority == Priority::Normal && local.len() < self.local_capacity {
+                    local.push_back(task);
+                } else {
+                    // Not Normal, or the local queue is full: let the injector serve it
+                    drop(local);
+                    self.injector.push(task);
+                }
+
+                // This worker may be busy for a while: if no worker is parked,
+                // the one blocked on the reactor has to come and steal the task
+                if !self.notify_one() && self.driving.load(Ordering::Acquire) {
+                    self.reactor.unpark();
+                }
+            }
+            _ => {
+                self.injector.push(task);
+
+                // Woken from outside the pool: when no worker is parked, the one
+                // blocked on the reactor has to pick the task up
+                if !self.notify_one() {
+                    self.reactor.unpark();
+                }
+            }
+        }
+    }
+}
+
+// Struct representing a single worker thread of the pool
+struct Worker {
+    shared: Arc<Shared>,
+    index: usize,
+}
+
+impl Worker {
+    // Function to run the worker loop on the current thread
+    fn run(self) -> Result<()> {
+        // Route spawns and I/O registrations made by tasks to this pool
+        WORKER.with(|worker| worker.set(Some((self.shared.id(), self.index))));
+        // set() skips the lazy default, which may use a backend the pool avoids
+        REACTOR.set(self.shared.reactor.clone());
+        executor::enter(self.shared.clone(), self.shared.blocking.clone());
+
+        let mut tick: u32 = 0;
+        while !self.shared.shutdown.load(Ordering::Acquire) {
+            tick = tick.wrapping_add(1);
+
+            if let Some(task) = self.next_task(tick) {
+                let started = Instant::now();
+                task.poll();
+                let elapsed = started.elapsed();
+                self.shared.stats.polled(task.priority, elapsed);
+                self.shared.workers[self.index].polled(elapsed);
+                self.shared.poll_times.record(elapsed);
+                task.poll_times.record(elapsed);
+
+                // Busy with ready tasks: look for I/O now and then, unless another worker waits for it
+                if tick.is_multiple_of(coop::EVENT_INTERVAL as u32)
+                    && !self.shared.driving.swap(true, Ordering::AcqRel)
+                {
+                    let wakers = self.shared.reactor.poll_now();
+                    self.shared.driving.store(false, Ordering::Release);
+                    // Only a broken poller fails a wait, no waker is lost then
+                    for waker in wakers? {
+                        waker.wake();
+                    }
+                }
+                continue;
+            }
+
+            // Nothing to run: wait for I/O unless another worker already does
+            if !self.shared.driving.swap(true, Ordering::AcqRel) {
+                self.shared.workers[self.index].park();
+                let wakers = self.shared.reactor.poll_wait();
+                self.shared.driving.store(false, Ordering::Release);
+                self.shared.workers[self.index].unpark();
+
+                // Let a parked worker take over the reactor while we run the woken tasks
+                self.shared.notify_one();
+                // Only a broken poller fails a wait, no waker is lost then
+                for waker in wakers? {
+                    waker.wake();
+                }
+                continue;
+            }
+
+            self.shared.workers[self.index].park();
+            self.park();
+            self.shared.workers[self.index].unpark();
+        }
+
+        Ok(())
+    }
+
+    // Function to pick the next task to run: local queue, injector, then
+    // stealing. High tasks waiting in the injector go before the local queue.
+    fn next_task(&self, tick: u32) -> Option<Arc<Task>> {
+        if tick.is_multiple_of(GLOBAL_QUEUE_INTERVAL)
+            || self.shared.injector.has_waiting(Priority::High)
+        {
+            if let Some(task) = self.shared.injector.pop() {
+                return Some(task);
+            }
+        }
+
+        if let Some(task) = self.shared.locals[self.index].lock().unwrap().pop_front() {
+            return Some(task);
+        }
+
+        if let Some(task) = self.shared.injector.pop() {
+            return Some(task);
+        }
+
+        self.steal()
+    }
+
+    // Function to steal half of the tasks from the first busy sibling
+    fn steal(&self) -> Option<Arc<Task>> {
+        let workers = self.shared.locals.len();
+
+        for offset in 1..workers {
+            let victim = (self.index + offset) % workers;
+            let mut stolen = {
+                let mut queue = self.shared.locals[victim].lock().unwrap();
+                let count = queue.len().div_ceil(2);
+                let at = queue.len() - count;
+                queue.split_off(at)
+            };
+
+            if let Some(task) = stolen.pop_front() {
+                self.shared.locals[self.index]
+                    .lock()
+                    .unwrap()
+                    .extend(stolen);
+                return Some(task);
+            }
+        }
+
+        None
+    }
+
+    // Function to park the worker until new work is scheduled or the reactor is free
+    fn park(&self) {
+        let mut sleepers = self.shared.sleepers.lock().unwrap();
+
+        // Re-check under the lock so a concurrent notify_one cannot be missed
+        if self.shared.has_work()
+            || !self.shared.driving.load(Ordering::Acquire)
+            || self.shared.shutdown.load(Ordering::Acquire)
+        {
+            return;
+        }
+
+        *sleepers += 1;
+        sleepers = self.shared.condvar.wait(sleepers).unwrap();
+        *sleepers -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::MultiThread;
+    use crate::runtime::executor;
+    use crate::runtime::sync::oneshot;
+
+    #[test]
+    fn blocked_worker_queue_is_stolen() {
+        let pool = MultiThread::new(2);
+        pool.start(Arc::new(|_| {})).unwrap();
+        let (done, finished) = mpsc::channel();
+
+        pool.spawn(async move {
+            let (ran, polled) = mpsc::channel();
+            for _ in 0..8 {
+                let ran = ran.clone();
+                executor::spawn(async move {
+                    ran.send(thread::current().id()).unwrap();
+                });
+            }
+
+            // The tasks were queued on this worker, which never yields: only
+            // the other worker stealing them can run them
+            let blocked = thread::current().id();
+            let stolen = (0..8).all(|_| {
+                polled
+                    .recv_timeout(Duration::from_secs(5))
+                    .is_ok_and(|worker| worker != blocked)
+            });
+            done.send(stolen).unwrap();
+        });
+
+        assert!(finished.recv_timeout(Duration::from_secs(10)).unwrap());
+        pool.shutdown();
+    }
+
+    #[test]
+    fn wake_from_outside_unparks_a_worker() {
+        let pool = MultiThread::new(2);
+        pool.start(Arc::new(|_| {})).unwrap();
+        let (tx, rx) = oneshot::channel();
+        let (done, finished) = mpsc::channel();
+
+        pool.spawn(async move {
+            done.send(rx.await.unwrap()).unwrap();
+        });
+
+        // Let every worker go idle before waking the task from this thread
+        thread::sleep(Duration::from_millis(100));
+        tx.send(7).unwrap();
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(7));
+        pool.shutdown();
+    }
+}
@@ -1,2 +1,90 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::core::{error::IOError, os, result::Result};
+use crate::runtime::polling::{Completion, Operation};
+use crate::runtime::reactor::{Reactor, REACTOR};
+use crate::runtime::time::Sleep;
+
 pub mod tcp_listener;
 pub mod tcp_stream;
+
+// Check whether an operation waiting on the optional deadline has timed out
+fn timed_out(deadline: &mut Option<Sleep>, cx: &mut Context<'_>) -> bool {
+    match deadline {
+        Some(sleep) => Pin::new(sleep).poll(cx).is_ready(),
+        None => false,
+    }
+}
+
+// Check whether the current reactor performs I/O operations itself
+fn completion_based() -> bool {
+    REACTOR.with(|current| current.borrow().completion_based())
+}
+
+// Drive an operation submitted to a completion-based reactor: submit it on the
+// first poll, then wait for its completion. Fails with TimedOut if the deadline
+// elapses and the operation is cancelled before it completes.
+fn poll_operation(
+    submitted: &mut Option<Submitted>,
+    deadline: &mut Option<Sleep>,
+    cx: &mut Context<'_>,
+    op: impl FnOnce() -> Operation,
+) -> Poll<Result<Completion>> {
+    let Submitted { reactor, token } = match submitted {
+        Some(submitted) => submitted,
+        None => {
+            let reactor = REACTOR.with(|current| current.borrow().clone());
+            match reactor.submit(op(), cx) {
+                Ok(token) => submitted.insert(Submitted { reactor, token }),
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    };
+
+    if let Poll::Ready(completion) = reactor.poll_operation(*token, cx) {
+        *submitted = None;
+
+        // Only the cancellation below fails an operation with ECANCELED
+        if completion.result == -libc::ECANCELED {
+            return Poll::Ready(Err(IOError::TimedOut));
+        }
+        return Poll::Ready(Ok(completion));
+    }
+
+    // Once the deadline elapses, ask for the operation to be cancelled and keep
+    // waiting for its completion: it may have succeeded meanwhile, and its
+    // result, e.g. an accepted connection, is returned instead of being lost
+    if timed_out(deadline, cx) {
+        reactor.interrupt_operation(*token);
+        *deadline = None;
+    }
+
+    Poll::Pending
+}
+
+// Operation submitted to a completion-based reactor, with that reactor: tokens
+// only mean something to the reactor that handed them out
+struct Submitted {
+    reactor: Arc<Reactor>,
+    token: u64,
+}
+
+// Cancel the operation still in flight when its future is dropped, whatever
+// thread drops it
+fn cancel_operation(submitted: &mut Option<Submitted>) {
+    if let Some(Submitted { reactor, token }) = submitted.take() {
+        reactor.cancel_operation(token);
+    }
+}
+
+// Convert the negated errno of a failed operation to an error
+fn operation_error(result: i32) -> IOError {
+    match -result {
+        libc::EAGAIN => IOError::WouldBlock,
+        libc::ECONNRESET => IOError::ConnectionReset,
+        errno => IOError::SyscallResult(os::OS::strerror(errno)),
+    }
+}
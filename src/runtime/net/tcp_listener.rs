@@ -1,45 +1,80 @@
 use std::future::Future;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use crate::core::error::IOError;
 use crate::core::result::Result;
 use crate::net::{self, SocketAddrV4};
-use crate::runtime::reactor::REACTOR;
+use crate::runtime::coop;
+use crate::runtime::polling::Operation;
+use crate::runtime::reactor::{Reactor, REACTOR};
+use crate::runtime::ready::Ready;
+use crate::runtime::sim;
+use crate::runtime::time::{self, Sleep};
 
 use super::tcp_stream::TcpStream;
+use super::{
+    cancel_operation, completion_based, operation_error, poll_operation, timed_out, Submitted,
+};
 
-// Struct representing a TCP listener
+// Struct representing a TCP listener, on the OS or on the simulated network
+// when bound inside a simulation
 pub struct TcpListener {
-    inner: net::TcpListener,
+    inner: Inner,
+    accept_timeout: Option<Duration>, // Limit for a single accept before it fails with TimedOut
 }
 
 impl TcpListener {
     // Constructor to create a TcpListener and bind it to a specific address
     pub fn bind(addr: SocketAddrV4) -> Result<TcpListener> {
+        if sim::is_current() {
+            return Ok(TcpListener {
+                inner: Inner::Simulated(sim::net::TcpListener::bind(addr)?),
+                accept_timeout: None,
+            });
+        }
+
         // Bind a network listener to the provided address
         let listener = net::TcpListener::bind(addr)?;
 
-        // Set the listener to non-blocking mode
-        listener.set_nonblocking()?;
+        // Set the listener to non-blocking mode, unless accepts are submitted to
+        // the reactor: io_uring fails them with EAGAIN on non-blocking sockets
+        if !completion_based() {
+            listener.set_nonblocking()?;
+        }
 
         // Register the listener with the reactor for event handling
-        REACTOR.with(|current| {
-            let current = current.borrow();
-            current
-                .register(listener.as_raw_fd(), libc::EPOLLIN)
-                .unwrap();
-        });
+        let reactor = REACTOR.with(|current| current.borrow().clone());
+        reactor
+            .register(listener.as_raw_fd(), libc::EPOLLIN)
+            .unwrap();
 
         // Return the TcpListener
-        Ok(TcpListener { inner: listener })
+        Ok(TcpListener {
+            inner: Inner::Os(listener, reactor),
+            accept_timeout: None,
+        })
+    }
+
+    // Set the time an accept may wait for a connection before failing, None to wait forever
+    pub fn set_accept_timeout(&mut self, timeout: Option<Duration>) {
+        self.accept_timeout = timeout;
+    }
+
+    // Get the accept timeout of the listener
+    pub fn accept_timeout(&self) -> Option<Duration> {
+        self.accept_timeout
     }
 
     // Function to initiate an accept operation on the listener
-    pub fn accept(&self) -> Accept {
+    pub fn accept(&self) -> Accept<'_> {
         Accept {
             listener: &self.inner,
+            deadline: self.accept_timeout.map(time::sleep),
+            op: None,
         }
     }
 }
@@ -49,35 +84,116 @@ impl<'listener> Future for Accept<'listener> {
     type Output = Result<(TcpStream, SocketAddrV4)>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.listener.accept() {
-            Ok((stream, addr)) => Poll::Ready(Ok((TcpStream::new(stream), addr))),
-            Err(err) if err == IOError::WouldBlock => {
-                println!("[accept] listener would block, pause the execution");
-
-                // Modify the reactor to wait for new events on the listener
-                REACTOR.with(|reactor| {
-                    let mut reactor = reactor.borrow_mut();
-                    reactor
-                        .modify(self.listener.as_raw_fd(), libc::EPOLLIN, cx)
-                        .unwrap();
-                });
-
-                Poll::Pending
-            }
-            Err(err) => Poll::Ready(Err(err)),
+        let state = self.get_mut();
+
+        // Count against the budget of the task, so a listener always ready cannot starve the others
+        coop::poll_budgeted(cx, |cx| match state.listener {
+            Inner::Simulated(listener) => match listener.poll_accept(cx) {
+                Poll::Ready(result) => {
+                    Poll::Ready(result.map(|(stream, addr)| (TcpStream::from(stream), addr)))
+                }
+                Poll::Pending if timed_out(&mut state.deadline, cx) => {
+                    Poll::Ready(Err(IOError::TimedOut))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            Inner::Os(..) if completion_based() => state.poll_completion(cx),
+            Inner::Os(..) => state.poll_readiness(cx),
+        })
+    }
+}
+
+// Socket behind a TcpListener
+enum Inner {
+    Os(net::TcpListener, Arc<Reactor>), // With the reactor it is registered with
+    Simulated(sim::net::TcpListener),
+}
+
+impl Inner {
+    fn os(&self) -> &net::TcpListener {
+        match self {
+            Inner::Os(listener, _) => listener,
+            Inner::Simulated(_) => panic!("a simulated listener has no file descriptor"),
         }
     }
 }
 
 // Struct representing an accept operation
 pub struct Accept<'listener> {
-    listener: &'listener net::TcpListener,
+    listener: &'listener Inner,
+    deadline: Option<Sleep>, // Fails the accept with TimedOut once elapsed
+    op: Option<Submitted>,   // The accept submitted to a completion-based reactor
 }
 
-// Implementation of AsRawFd for TcpListener
+impl Accept<'_> {
+    // Accept through an operation submitted to the reactor
+    fn poll_completion(&mut self, cx: &mut Context<'_>) -> Poll<Result<(TcpStream, SocketAddrV4)>> {
+        let fd = self.listener.os().as_raw_fd();
+        let completion =
+            match poll_operation(&mut self.op, &mut self.deadline, cx, || Operation::Accept {
+                fd,
+                addr: Box::new(unsafe { std::mem::zeroed() }),
+                addr_len: Box::new(std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t),
+            }) {
+                Poll::Ready(Ok(completion)) => completion,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+        match (completion.result, completion.operation) {
+            (client_socket, Operation::Accept { addr, .. }) if client_socket >= 0 => {
+                // Build the stream and address the same way net::TcpListener::accept does
+                let stream = net::TcpStream::new(client_socket);
+                let addr = SocketAddrV4::new(addr.sin_addr.s_addr.to_be_bytes(), addr.sin_port);
+                Poll::Ready(Ok((TcpStream::new(stream), addr)))
+            }
+            (result, _) => Poll::Ready(Err(operation_error(result))),
+        }
+    }
+
+    // Accept as soon as the reactor reports pending connections
+    fn poll_readiness(&mut self, cx: &mut Context<'_>) -> Poll<Result<(TcpStream, SocketAddrV4)>> {
+        loop {
+            match self.listener.os().accept() {
+                Ok((stream, addr)) => return Poll::Ready(Ok((TcpStream::new(stream), addr))),
+                Err(IOError::WouldBlock) => {
+                    if timed_out(&mut self.deadline, cx) {
+                        return Poll::Ready(Err(IOError::TimedOut));
+                    }
+
+                    println!("[accept] listener would block, pause the execution");
+
+                    // Wait for the reactor to report new connections on the listener
+                    let fd = self.listener.os().as_raw_fd();
+                    let ready = REACTOR.with(|reactor| {
+                        let reactor = reactor.borrow();
+                        reactor.clear_readiness(fd, Ready::READABLE);
+                        reactor.poll_read_ready(fd, cx)
+                    });
+
+                    match ready {
+                        Poll::Ready(Ok(_)) => continue, // Became ready meanwhile, retry
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+// Cancel the accept still in flight when the future is dropped
+impl Drop for Accept<'_> {
+    fn drop(&mut self) {
+        cancel_operation(&mut self.op);
+    }
+}
+
+// Implementation of AsRawFd for TcpListener. Panics for a simulated listener.
 impl AsRawFd for TcpListener {
     fn as_raw_fd(&self) -> RawFd {
-        self.inner.as_raw_fd()
+        self.inner.os().as_raw_fd()
     }
 }
 
@@ -88,12 +204,12 @@ impl AsFd for TcpListener {
     }
 }
 
-// Drop implementation to remove the TcpListener from the reactor on destruction
+// Drop implementation to remove the TcpListener from the reactor it is
+// registered with, whatever thread drops it
 impl Drop for TcpListener {
     fn drop(&mut self) {
-        REACTOR.with(|current| {
-            let mut current = current.borrow_mut();
-            current.remove(self.inner.as_raw_fd());
-        });
+        if let Inner::Os(listener, reactor) = &self.inner {
+            reactor.remove(listener.as_raw_fd());
+        }
     }
 }
@@ -1,53 +1,273 @@
 use std::future::Future;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use crate::core::error::IOError;
 use crate::core::result::Result;
 use crate::net;
-use crate::runtime::reactor::REACTOR;
+use crate::runtime::coop;
+use crate::runtime::polling::Operation;
+use crate::runtime::reactor::{Reactor, REACTOR};
+use crate::runtime::ready::Ready;
+use crate::runtime::sim;
+use crate::runtime::time::{self, Sleep};
 
-// Struct representing a TCP stream
+use super::{
+    cancel_operation, completion_based, operation_error, poll_operation, timed_out, Submitted,
+};
+
+// Struct representing a TCP stream, on the OS or on the simulated network
+// when created inside a simulation
 pub struct TcpStream {
-    inner: net::TcpStream,
+    inner: Inner,
+    read_timeout: Option<Duration>, // Limit for a single read before it fails with TimedOut
+    write_timeout: Option<Duration>, // Limit for a single write before it fails with TimedOut
 }
 
 impl TcpStream {
     // Constructor to create a TcpStream and register it with the reactor
     pub fn new(stream: net::TcpStream) -> TcpStream {
         // Register the stream with the reactor for both read and write events
-        REACTOR.with(|current| {
-            let current = current.borrow();
-            current
-                .register(stream.as_raw_fd(), libc::EPOLLIN | libc::EPOLLOUT)
-                .unwrap();
-        });
+        let reactor = REACTOR.with(|current| current.borrow().clone());
+        reactor
+            .register(stream.as_raw_fd(), libc::EPOLLIN | libc::EPOLLOUT)
+            .unwrap();
+
+        TcpStream {
+            inner: Inner::Os(stream, reactor),
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+
+    // Set the time a read may wait for data before failing, None to wait forever
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    // Get the read timeout of the stream
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    // Set the time a write may wait for buffer space before failing, None to wait forever
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
 
-        TcpStream { inner: stream }
+    // Get the write timeout of the stream
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
     }
 
     // Function to initiate a read operation on the stream
     pub fn read<'a>(&'a mut self, buff: &'a mut [u8]) -> ReadFuture<'a> {
-        ReadFuture {
-            stream: &mut self.inner,
-            buff: buff.as_mut(),
-        }
+        self.read_future(buff)
     }
 
     // Function to initiate a write operation on the stream
     pub fn write<'a>(&'a mut self, buff: &'a [u8]) -> WriteFuture<'a> {
+        self.write_future(buff)
+    }
+
+    // Function to split the stream into a read half and a write half that can
+    // wait on the socket at the same time
+    pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
+        (ReadHalf { stream: self }, WriteHalf { stream: self })
+    }
+
+    // Function to split the stream into owned halves that can be moved to
+    // different tasks. The socket is closed once both halves are dropped.
+    pub fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        let stream = Arc::new(self);
+        (
+            OwnedReadHalf {
+                stream: stream.clone(),
+            },
+            OwnedWriteHalf { stream },
+        )
+    }
+
+    // Function to check whether the stream is ready for reading, registering the
+    // task waker otherwise. The returned set tells apart data, peer half-close and errors.
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<Ready>> {
+        match &self.inner {
+            Inner::Os(stream, _) => {
+                REACTOR.with(|current| current.borrow().poll_read_ready(stream.as_raw_fd(), cx))
+            }
+            Inner::Simulated(stream) => stream.poll_read_ready(cx),
+        }
+    }
+
+    // Function to check whether the stream is ready for writing, registering the
+    // task waker otherwise. The returned set tells apart buffer space, hangup and errors.
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<Result<Ready>> {
+        match &self.inner {
+            Inner::Os(stream, _) => {
+                REACTOR.with(|current| current.borrow().poll_write_ready(stream.as_raw_fd(), cx))
+            }
+            Inner::Simulated(stream) => stream.poll_write_ready(cx),
+        }
+    }
+
+    fn read_future<'a>(&'a self, buff: &'a mut [u8]) -> ReadFuture<'a> {
+        ReadFuture {
+            stream: &self.inner,
+            buff,
+            deadline: self.read_timeout.map(time::sleep),
+            op: None,
+        }
+    }
+
+    fn write_future<'a>(&'a self, buff: &'a [u8]) -> WriteFuture<'a> {
         WriteFuture {
-            stream: &mut self.inner,
-            buff: buff.as_ref(),
+            stream: &self.inner,
+            buff,
+            deadline: self.write_timeout.map(time::sleep),
+            op: None,
+        }
+    }
+}
+
+// Socket behind a TcpStream
+enum Inner {
+    Os(net::TcpStream, Arc<Reactor>), // With the reactor it is registered with
+    Simulated(sim::net::TcpStream),
+}
+
+impl Inner {
+    fn os(&self) -> &net::TcpStream {
+        match self {
+            Inner::Os(stream, _) => stream,
+            Inner::Simulated(_) => panic!("a simulated stream has no file descriptor"),
         }
     }
 }
 
+// Implementation of From to use a stream of the simulated network as a TcpStream
+impl From<sim::net::TcpStream> for TcpStream {
+    fn from(stream: sim::net::TcpStream) -> TcpStream {
+        TcpStream {
+            inner: Inner::Simulated(stream),
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+}
+
+// Struct representing the read half of a borrowed TcpStream
+pub struct ReadHalf<'a> {
+    stream: &'a TcpStream,
+}
+
+impl ReadHalf<'_> {
+    // Function to initiate a read operation on the stream
+    pub fn read<'a>(&'a mut self, buff: &'a mut [u8]) -> ReadFuture<'a> {
+        self.stream.read_future(buff)
+    }
+}
+
+// Struct representing the write half of a borrowed TcpStream
+pub struct WriteHalf<'a> {
+    stream: &'a TcpStream,
+}
+
+impl WriteHalf<'_> {
+    // Function to initiate a write operation on the stream
+    pub fn write<'a>(&'a mut self, buff: &'a [u8]) -> WriteFuture<'a> {
+        self.stream.write_future(buff)
+    }
+}
+
+// Struct representing the read half of a TcpStream, owning its share of the socket
+pub struct OwnedReadHalf {
+    stream: Arc<TcpStream>,
+}
+
+impl OwnedReadHalf {
+    // Function to initiate a read operation on the stream
+    pub fn read<'a>(&'a mut self, buff: &'a mut [u8]) -> ReadFuture<'a> {
+        self.stream.read_future(buff)
+    }
+}
+
+// Struct representing the write half of a TcpStream, owning its share of the socket
+pub struct OwnedWriteHalf {
+    stream: Arc<TcpStream>,
+}
+
+impl OwnedWriteHalf {
+    // Function to initiate a write operation on the stream
+    pub fn write<'a>(&'a mut self, buff: &'a [u8]) -> WriteFuture<'a> {
+        self.stream.write_future(buff)
+    }
+}
+
 // Future for handling asynchronous read operations
 pub struct ReadFuture<'a> {
-    stream: &'a mut net::TcpStream,
+    stream: &'a Inner,
     buff: &'a mut [u8],
+    deadline: Option<Sleep>, // Fails the read with TimedOut once elapsed
+    op: Option<Submitted>,   // The read submitted to a completion-based reactor
+}
+
+impl ReadFuture<'_> {
+    // Read through an operation submitted to the reactor, copying the data out of its buffer
+    fn poll_completion(&mut self, cx: &mut Context<'_>) -> Poll<Result<isize>> {
+        let fd = self.stream.os().as_raw_fd();
+        let len = self.buff.len();
+        let completion =
+            match poll_operation(&mut self.op, &mut self.deadline, cx, || Operation::Read {
+                fd,
+                buf: vec![0; len],
+            }) {
+                Poll::Ready(Ok(completion)) => completion,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+        match (completion.result, completion.operation) {
+            (0, _) => Poll::Ready(Err(IOError::ConnectionClosed)),
+            (n, Operation::Read { buf, .. }) if n > 0 => {
+                let n = n as usize;
+                self.buff[..n].copy_from_slice(&buf[..n]);
+                Poll::Ready(Ok(n as isize))
+            }
+            (result, _) => Poll::Ready(Err(operation_error(result))),
+        }
+    }
+
+    // Read as soon as the reactor reports the stream readable
+    fn poll_readiness(&mut self, cx: &mut Context<'_>) -> Poll<Result<isize>> {
+        loop {
+            match self.stream.os().read(self.buff) {
+                Ok(n) => return Poll::Ready(Ok(n)),
+                Err(IOError::WouldBlock) => {
+                    if timed_out(&mut self.deadline, cx) {
+                        return Poll::Ready(Err(IOError::TimedOut));
+                    }
+
+                    // The readiness we had is stale, wait for the reactor to report a new one
+                    let fd = self.stream.os().as_raw_fd();
+                    let ready = REACTOR.with(|current| {
+                        let current = current.borrow();
+                        current.clear_readiness(fd, Ready::READABLE);
+                        current.poll_read_ready(fd, cx)
+                    });
+
+                    match ready {
+                        Poll::Ready(Ok(_)) => continue, // Became ready meanwhile, retry
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
 }
 
 impl<'a> Future for ReadFuture<'a> {
@@ -56,27 +276,77 @@ impl<'a> Future for ReadFuture<'a> {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let state = self.get_mut();
 
-        match state.stream.read(&mut state.buff) {
-            Ok(n) => Poll::Ready(Ok(n)),
-            Err(e) if e == IOError::WouldBlock => {
-                // Re-register with the reactor to wait for read events
-                REACTOR.with(|current| {
-                    current
-                        .borrow_mut()
-                        .modify(state.stream.as_raw_fd(), libc::EPOLLIN, cx)
-                        .unwrap();
-                });
-                Poll::Pending
-            }
-            Err(e) => Poll::Ready(Err(e)),
-        }
+        // Count against the budget of the task, so a stream always ready cannot starve the others
+        coop::poll_budgeted(cx, |cx| match state.stream {
+            Inner::Simulated(stream) => match stream.poll_read(cx, state.buff) {
+                Poll::Pending if timed_out(&mut state.deadline, cx) => {
+                    Poll::Ready(Err(IOError::TimedOut))
+                }
+                poll => poll,
+            },
+            Inner::Os(..) if completion_based() => state.poll_completion(cx),
+            Inner::Os(..) => state.poll_readiness(cx),
+        })
     }
 }
 
 // Future for handling asynchronous write operations
 pub struct WriteFuture<'a> {
-    stream: &'a mut net::TcpStream,
+    stream: &'a Inner,
     buff: &'a [u8],
+    deadline: Option<Sleep>, // Fails the write with TimedOut once elapsed
+    op: Option<Submitted>,   // The write submitted to a completion-based reactor
+}
+
+impl WriteFuture<'_> {
+    // Write through an operation submitted to the reactor, from a copy of the data
+    fn poll_completion(&mut self, cx: &mut Context<'_>) -> Poll<Result<isize>> {
+        let fd = self.stream.os().as_raw_fd();
+        let buff = self.buff;
+        let completion =
+            match poll_operation(&mut self.op, &mut self.deadline, cx, || Operation::Write {
+                fd,
+                buf: buff.to_vec(),
+            }) {
+                Poll::Ready(Ok(completion)) => completion,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+        match completion.result {
+            n if n >= 0 => Poll::Ready(Ok(n as isize)),
+            result => Poll::Ready(Err(operation_error(result))),
+        }
+    }
+
+    // Write as soon as the reactor reports the stream writable
+    fn poll_readiness(&mut self, cx: &mut Context<'_>) -> Poll<Result<isize>> {
+        loop {
+            match self.stream.os().write(self.buff) {
+                Ok(n) => return Poll::Ready(Ok(n)),
+                Err(IOError::WouldBlock) => {
+                    if timed_out(&mut self.deadline, cx) {
+                        return Poll::Ready(Err(IOError::TimedOut));
+                    }
+
+                    // The readiness we had is stale, wait for the reactor to report a new one
+                    let fd = self.stream.os().as_raw_fd();
+                    let ready = REACTOR.with(|current| {
+                        let current = current.borrow();
+                        current.clear_readiness(fd, Ready::WRITABLE);
+                        current.poll_write_ready(fd, cx)
+                    });
+
+                    match ready {
+                        Poll::Ready(Ok(_)) => continue, // Became ready meanwhile, retry
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
 }
 
 impl<'a> Future for WriteFuture<'a> {
@@ -85,27 +355,33 @@ impl<'a> Future for WriteFuture<'a> {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let state = self.get_mut();
 
-        match state.stream.write(&state.buff) {
-            Ok(n) => Poll::Ready(Ok(n)),
-            Err(e) if e == IOError::WouldBlock => {
-                // Re-register with the reactor to wait for write events
-                REACTOR.with(|current| {
-                    current
-                        .borrow_mut()
-                        .modify(state.stream.as_raw_fd(), libc::EPOLLOUT, cx)
-                        .unwrap();
-                });
-                Poll::Pending
-            }
-            Err(e) => Poll::Ready(Err(e)),
-        }
+        // Count against the budget of the task, so a stream always ready cannot starve the others
+        coop::poll_budgeted(cx, |cx| match state.stream {
+            Inner::Simulated(stream) => stream.poll_write(state.buff),
+            Inner::Os(..) if completion_based() => state.poll_completion(cx),
+            Inner::Os(..) => state.poll_readiness(cx),
+        })
+    }
+}
+
+// Cancel the read still in flight when the future is dropped
+impl Drop for ReadFuture<'_> {
+    fn drop(&mut self) {
+        cancel_operation(&mut self.op);
     }
 }
 
-// Implementation of AsRawFd for TcpStream
+// Cancel the write still in flight when the future is dropped
+impl Drop for WriteFuture<'_> {
+    fn drop(&mut self) {
+        cancel_operation(&mut self.op);
+    }
+}
+
+// Implementation of AsRawFd for TcpStream. Panics for a simulated stream.
 impl AsRawFd for TcpStream {
     fn as_raw_fd(&self) -> RawFd {
-        self.inner.as_raw_fd()
+        self.inner.os().as_raw_fd()
     }
 }
 
@@ -116,12 +392,12 @@ impl AsFd for TcpStream {
     }
 }
 
-// Drop implementation to remove the TcpStream from the reactor on destruction
+// Drop implementation to remove the TcpStream from the reactor it is registered
+// with, whatever thread drops it
 impl Drop for TcpStream {
     fn drop(&mut self) {
-        REACTOR.with(|current| {
-            let mut current = current.borrow_mut();
-            current.remove(self.inner.fd())
-        })
+        if let Inner::Os(stream, reactor) = &self.inner {
+            reactor.remove(stream.fd())
+        }
     }
 }
@@ -1,17 +1,14 @@
-use libc::{epoll_event, EPOLLIN, EPOLLOUT, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD};
+use libc::{epoll_event, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD};
 use std::os::fd::RawFd;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use super::{Event, Events};
 use crate::core::{error::IOError, os, result::Result};
 
-#[derive(Clone)]
-pub struct Event {
-    pub key: RawFd,
-    pub readable: bool,
-    pub writable: bool,
-}
-
 pub struct Poller {
     epoll_fd: RawFd,
+    raw: Mutex<Vec<epoll_event>>, // Buffer handed to epoll_wait, reused across waits
 }
 
 impl Poller {
@@ -22,12 +19,17 @@ impl Poller {
             return Err(IOError::SyscallResult(os::OS::err_msg()));
         }
 
-        Ok(Poller { epoll_fd })
+        Ok(Poller {
+            epoll_fd,
+            raw: Mutex::new(Vec::new()),
+        })
     }
+}
 
-    pub fn add(&self, fd: RawFd, events: u32) -> Result<()> {
+impl super::Poller for Poller {
+    fn add(&self, fd: RawFd, events: u32) -> Result<()> {
         let mut event = libc::epoll_event {
-            events: events,
+            events,
             u64: fd as u64,
         };
 
@@ -40,9 +42,9 @@ impl Poller {
         Ok(())
     }
 
-    pub fn modify(&self, fd: RawFd, events: u32) -> Result<()> {
+    fn modify(&self, fd: RawFd, events: u32) -> Result<()> {
         let mut event = libc::epoll_event {
-            events: events,
+            events,
             u64: fd as u64,
         };
 
@@ -55,27 +57,63 @@ impl Poller {
         Ok(())
     }
 
-    pub fn delete(&self, fd: RawFd) {
+    fn delete(&self, fd: RawFd) {
         unsafe {
             let mut event: epoll_event = std::mem::zeroed();
             libc::epoll_ctl(self.epoll_fd, EPOLL_CTL_DEL, fd, &mut event);
         }
     }
 
-    pub fn wait(&self) -> Result<Vec<Event>> {
-        let mut events: [epoll_event; 1024] = unsafe { std::mem::zeroed() };
-        let num_events = unsafe { libc::epoll_wait(self.epoll_fd, events.as_mut_ptr(), 1024, -1) };
-
-        if num_events == -1 {
-            return Err(IOError::SyscallResult(os::OS::err_msg()));
+    // Wait for events and store them in the buffer, blocking at most for the
+    // given timeout or forever if None. Interrupted waits are retried.
+    fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut raw = self.raw.lock().unwrap();
+        raw.clear();
+        raw.reserve_exact(events.capacity());
+        events.clear();
+
+        loop {
+            let timeout = match deadline {
+                // Round up so we never wake up before a timer is due
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_nanos()
+                    .div_ceil(1_000_000)
+                    .min(i32::MAX as u128) as i32,
+                None => -1,
+            };
+
+            let num_events = unsafe {
+                libc::epoll_wait(
+                    self.epoll_fd,
+                    raw.as_mut_ptr(),
+                    events.capacity().min(i32::MAX as usize) as i32,
+                    timeout,
+                )
+            };
+
+            if num_events == -1 {
+                // A signal interrupted the wait, wait again for the remaining time
+                if os::OS::err_no() == libc::EINTR {
+                    continue;
+                }
+                return Err(IOError::SyscallResult(os::OS::err_msg()));
+            }
+
+            // The kernel initialised the first num_events entries
+            unsafe { raw.set_len(num_events as usize) };
+            for event in raw.iter() {
+                events.push(Event::from_mask(event.u64 as RawFd, event.events));
+            }
+            return Ok(());
         }
+    }
+}
 
-        Ok(events
-            .map(|event| Event {
-                key: event.u64 as RawFd,
-                readable: event.events & EPOLLIN as u32 != 0,
-                writable: event.events & EPOLLOUT as u32 != 0,
-            })
-            .to_vec())
+impl Drop for Poller {
+    // Close the epoll instance when the poller is dropped
+    fn drop(&mut self) {
+        unsafe { libc::close(self.epoll_fd) };
     }
 }
@@ -1 +1,187 @@
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use libc::{sockaddr_in, socklen_t, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLRDHUP};
+
+use crate::core::{error::IOError, result::Result};
+
 pub mod epoll;
+pub mod poll;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+
+// Event notification backends the Reactor can be driven by. Defaults to
+// io_uring when compiled in, epoll otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[cfg_attr(not(feature = "io-uring"), default)]
+    Epoll,
+    Poll, // Portable fallback for environments forbidding epoll
+    #[cfg(feature = "io-uring")]
+    #[default]
+    IoUring,
+}
+
+// Trait implemented by the event notification backends driving the Reactor.
+// Interests are expressed with the EPOLL* flags whatever the backend; every
+// registration is one-shot and has to be re-armed with modify after it fired.
+pub trait Poller: Send + Sync {
+    // Start watching a file descriptor for the given interest
+    fn add(&self, fd: RawFd, events: u32) -> Result<()>;
+
+    // Re-arm a file descriptor with a new interest
+    fn modify(&self, fd: RawFd, events: u32) -> Result<()>;
+
+    // Stop watching a file descriptor
+    fn delete(&self, fd: RawFd);
+
+    // Wait for events and store them in the buffer, blocking at most for the
+    // given timeout or forever if None
+    fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> Result<()>;
+
+    // Check whether the backend performs I/O operations itself (completion-based)
+    fn supports_operations(&self) -> bool {
+        false
+    }
+
+    // Submit an operation, reported in Events::completions under the given token
+    fn submit(&self, _token: u64, _op: Operation) -> Result<()> {
+        Err(IOError::SyscallResult(
+            "backend does not support submitted operations".to_string(),
+        ))
+    }
+
+    // Ask the backend to cancel a submitted operation. Its completion is still reported.
+    fn cancel(&self, _token: u64) {}
+}
+
+// Function to create a poller for the given backend
+pub fn new_poller(backend: Backend) -> Result<Box<dyn Poller>> {
+    let poller: Box<dyn Poller> = match backend {
+        Backend::Epoll => Box::new(epoll::Poller::new()?),
+        Backend::Poll => Box::new(poll::Poller::new()?),
+        #[cfg(feature = "io-uring")]
+        Backend::IoUring => Box::new(uring::Poller::new()?),
+    };
+
+    Ok(poller)
+}
+
+#[derive(Clone)]
+pub struct Event {
+    pub key: RawFd,
+    pub readable: bool,
+    pub writable: bool,
+    pub error: bool,       // EPOLLERR: an error is pending on the descriptor
+    pub hangup: bool,      // EPOLLHUP: both directions of the connection are closed
+    pub read_closed: bool, // EPOLLRDHUP: the peer shut down its writing side
+}
+
+impl Event {
+    // Build an event from a mask of EPOLL* (or the identical POLL*) flags
+    pub fn from_mask(key: RawFd, mask: u32) -> Event {
+        Event {
+            key,
+            readable: mask & EPOLLIN as u32 != 0,
+            writable: mask & EPOLLOUT as u32 != 0,
+            error: mask & EPOLLERR as u32 != 0,
+            hangup: mask & EPOLLHUP as u32 != 0,
+            read_closed: mask & EPOLLRDHUP as u32 != 0,
+        }
+    }
+}
+
+// I/O operation performed by a completion-based backend. Buffers are owned by
+// the operation so they stay valid until the kernel is done with them.
+pub enum Operation {
+    Accept {
+        fd: RawFd,
+        addr: Box<sockaddr_in>, // Filled with the peer address
+        addr_len: Box<socklen_t>,
+    },
+    Read {
+        fd: RawFd,
+        buf: Vec<u8>, // Filled with the data read
+    },
+    Write {
+        fd: RawFd,
+        buf: Vec<u8>, // Data to write
+    },
+}
+
+// Struct representing the outcome of a submitted operation
+pub struct Completion {
+    pub token: u64,           // Token the operation was submitted with
+    pub result: i32,          // Syscall-like result, negated errno on failure
+    pub operation: Operation, // The operation, handing its buffers back
+}
+
+// Struct representing a reusable buffer filled by Poller::wait. Only the events
+// returned by the last wait are exposed.
+pub struct Events {
+    list: Vec<Event>,
+    completions: Vec<Completion>,
+    capacity: usize,
+}
+
+impl Events {
+    // Constructor to create a buffer receiving at most `capacity` events per wait
+    pub fn with_capacity(capacity: usize) -> Events {
+        assert!(
+            capacity > 0,
+            "an event buffer needs room for at least one event"
+        );
+
+        Events {
+            list: Vec::with_capacity(capacity),
+            completions: Vec::new(),
+            capacity,
+        }
+    }
+
+    // Maximum number of events a single wait can return
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Number of events returned by the last wait
+    pub fn len(&self) -> usize {
+        self.list.len() + self.completions.len()
+    }
+
+    // Check whether the last wait returned no event
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.completions.is_empty()
+    }
+
+    // Iterate over the readiness events returned by the last wait
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        self.list.iter().cloned()
+    }
+
+    // Take the operation completions returned by the last wait
+    pub fn drain_completions(&mut self) -> impl Iterator<Item = Completion> + '_ {
+        self.completions.drain(..)
+    }
+
+    // Forget the events returned by the last wait
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.completions.clear();
+    }
+
+    // Check whether the buffer has room for another event
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    // Store an event reported by the backend
+    pub fn push(&mut self, event: Event) {
+        self.list.push(event);
+    }
+
+    // Store a completion reported by the backend
+    pub fn push_completion(&mut self, completion: Completion) {
+        self.completions.push(completion);
+    }
+}
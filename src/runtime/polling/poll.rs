@@ -0,0 +1,270 @@
+use libc::{pollfd, EPOLLIN, EPOLLOUT, EPOLLRDHUP, POLLERR, POLLIN, POLLNVAL};
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Event, Events};
+use crate::core::{error::IOError, os, result::Result};
+
+// Struct representing a poll(2) based poller, for environments where epoll is
+// not available. One-shot registrations are emulated by forgetting the interest
+// of a descriptor once it has been reported.
+pub struct Poller {
+    interests: Mutex<HashMap<RawFd, u32>>, // Armed interest of every descriptor
+    fds: Mutex<Vec<pollfd>>,               // Buffer handed to poll, reused across waits
+    waiting: AtomicBool,                   // Set while a thread is blocked in poll
+    wake_read: RawFd,                      // Read end of the pipe interrupting a wait
+    wake_write: RawFd,                     // Write end of the pipe interrupting a wait
+}
+
+impl Poller {
+    pub fn new() -> Result<Poller> {
+        let mut pipe = [0; 2];
+        let result = unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+
+        if result == -1 {
+            return Err(IOError::SyscallResult(os::OS::err_msg()));
+        }
+
+        Ok(Poller {
+            interests: Mutex::new(HashMap::new()),
+            fds: Mutex::new(Vec::new()),
+            waiting: AtomicBool::new(false),
+            wake_read: pipe[0],
+            wake_write: pipe[1],
+        })
+    }
+
+    // Store the interest of a descriptor. poll only sees the descriptors present
+    // when the wait started, so a blocked wait is interrupted to pick it up.
+    fn arm(&self, fd: RawFd, events: u32) {
+        self.interests.lock().unwrap().insert(fd, events);
+
+        if self.waiting.load(Ordering::Acquire) {
+            unsafe { libc::write(self.wake_write, [1u8].as_ptr() as *const libc::c_void, 1) };
+        }
+    }
+
+    // Empty the wake-up pipe
+    fn drain(&self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.wake_read,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if read <= 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl super::Poller for Poller {
+    fn add(&self, fd: RawFd, events: u32) -> Result<()> {
+        self.arm(fd, events);
+        Ok(())
+    }
+
+    fn modify(&self, fd: RawFd, events: u32) -> Result<()> {
+        self.arm(fd, events);
+        Ok(())
+    }
+
+    fn delete(&self, fd: RawFd) {
+        self.interests.lock().unwrap().remove(&fd);
+    }
+
+    // Wait for events and store them in the buffer, blocking at most for the
+    // given timeout or forever if None. Interrupted waits are retried.
+    fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut fds = self.fds.lock().unwrap();
+        events.clear();
+
+        // Announce the wait before taking the snapshot, so an interest armed
+        // after it always interrupts the wait
+        self.waiting.store(true, Ordering::Release);
+
+        fds.clear();
+        fds.push(pollfd {
+            fd: self.wake_read,
+            events: POLLIN,
+            revents: 0,
+        });
+        for (&fd, &interest) in self.interests.lock().unwrap().iter() {
+            // The POLL* flags have the same values as their EPOLL* counterparts
+            let interest = interest & (EPOLLIN | EPOLLOUT | EPOLLRDHUP) as u32;
+            if interest != 0 {
+                fds.push(pollfd {
+                    fd,
+                    events: interest as i16,
+                    revents: 0,
+                });
+            }
+        }
+
+        let result = loop {
+            let timeout = match deadline {
+                // Round up so we never wake up before a timer is due
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_nanos()
+                    .div_ceil(1_000_000)
+                    .min(i32::MAX as u128) as i32,
+                None => -1,
+            };
+
+            let result =
+                unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+
+            // A signal interrupted the wait, wait again for the remaining time
+            if result == -1 && os::OS::err_no() == libc::EINTR {
+                continue;
+            }
+            break result;
+        };
+
+        self.waiting.store(false, Ordering::Release);
+
+        if result == -1 {
+            return Err(IOError::SyscallResult(os::OS::err_msg()));
+        }
+
+        if fds[0].revents != 0 {
+            self.drain();
+        }
+
+        let mut interests = self.interests.lock().unwrap();
+        for pfd in fds[1..].iter().filter(|pfd| pfd.revents != 0) {
+            if events.is_full() {
+                break; // poll is level-triggered, the rest is reported by the next wait
+            }
+
+            // Skip descriptors deleted or already reported meanwhile, disarm the others
+            if interests.remove(&pfd.fd).is_none() {
+                continue;
+            }
+
+            let mut mask = pfd.revents as u16 as u32;
+            if pfd.revents & POLLNVAL != 0 {
+                mask |= POLLERR as u32;
+            }
+            events.push(Event::from_mask(pfd.fd, mask));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Poller {
+    // Close the wake-up pipe when the poller is dropped
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.wake_read);
+            libc::close(self.wake_write);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::Poller;
+    use crate::runtime::polling::{Event, Events, Poller as _};
+
+    // Wait for a short while, returning the events reported for the descriptor
+    fn wait_for(poller: &Poller, fd: i32, timeout: Duration) -> Vec<Event> {
+        let mut events = Events::with_capacity(16);
+        poller.wait(&mut events, Some(timeout)).unwrap();
+        events.iter().filter(|event| event.key == fd).collect()
+    }
+
+    #[test]
+    fn registrations_are_one_shot() {
+        let poller = Poller::new().unwrap();
+        let (mut local, remote) = UnixStream::pair().unwrap();
+        let fd = remote.as_raw_fd();
+
+        poller.add(fd, libc::EPOLLIN as u32).unwrap();
+        assert!(wait_for(&poller, fd, Duration::from_millis(10)).is_empty());
+
+        local.write_all(b"x").unwrap();
+        let events = wait_for(&poller, fd, Duration::from_secs(5));
+        assert!(events.len() == 1 && events[0].readable);
+
+        // Reported once, then forgotten until re-armed with modify
+        assert!(wait_for(&poller, fd, Duration::from_millis(10)).is_empty());
+        poller.modify(fd, libc::EPOLLIN as u32).unwrap();
+        let events = wait_for(&poller, fd, Duration::from_secs(5));
+        assert!(events.len() == 1 && events[0].readable);
+    }
+
+    #[test]
+    fn modify_replaces_the_interest() {
+        let poller = Poller::new().unwrap();
+        let (_local, remote) = UnixStream::pair().unwrap();
+        let fd = remote.as_raw_fd();
+
+        // Nothing to read, but the socket can be written to
+        poller.add(fd, libc::EPOLLIN as u32).unwrap();
+        assert!(wait_for(&poller, fd, Duration::from_millis(10)).is_empty());
+
+        poller.modify(fd, libc::EPOLLOUT as u32).unwrap();
+        let events = wait_for(&poller, fd, Duration::from_secs(5));
+        assert!(events.len() == 1 && events[0].writable && !events[0].readable);
+    }
+
+    #[test]
+    fn deleted_descriptors_are_not_reported() {
+        let poller = Poller::new().unwrap();
+        let (mut local, remote) = UnixStream::pair().unwrap();
+        let fd = remote.as_raw_fd();
+
+        poller.add(fd, libc::EPOLLIN as u32).unwrap();
+        poller.delete(fd);
+        local.write_all(b"x").unwrap();
+        assert!(wait_for(&poller, fd, Duration::from_millis(10)).is_empty());
+    }
+
+    #[test]
+    fn arming_interrupts_a_blocked_wait() {
+        let poller = Arc::new(Poller::new().unwrap());
+        let (mut local, remote) = UnixStream::pair().unwrap();
+        let fd = remote.as_raw_fd();
+        local.write_all(b"x").unwrap();
+
+        let waiter = {
+            let poller = poller.clone();
+            thread::spawn(move || {
+                let started = Instant::now();
+                let events = wait_for(&poller, fd, Duration::from_secs(10));
+                (started.elapsed(), events)
+            })
+        };
+
+        // Arm the descriptor once the other thread is blocked without it: the
+        // pipe wakes the wait up, and the next one picks the descriptor up
+        thread::sleep(Duration::from_millis(100));
+        poller.add(fd, libc::EPOLLIN as u32).unwrap();
+        let (elapsed, mut events) = waiter.join().unwrap();
+        assert!(elapsed < Duration::from_secs(5));
+
+        // Unless the wait only started after the descriptor was armed
+        if events.is_empty() {
+            events = wait_for(&poller, fd, Duration::from_secs(5));
+        }
+        assert!(events.len() == 1 && events[0].readable);
+    }
+}
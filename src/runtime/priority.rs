@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Scheduling class of a task, chosen when it is spawned. While several classes
+// have tasks waiting, run queues split their picks between the classes in
+// proportion to their weights, whatever the number of tasks in each: admin
+// connections spawned High keep being served when Normal data-plane connections
+// saturate the executor, and Low work gets the smallest share without starving.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    // Every class, from the highest
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    // Number of tasks of the class a run queue picks per round while every class has tasks waiting
+    pub fn weight(self) -> u32 {
+        match self {
+            Priority::High => 8,
+            Priority::Normal => 4,
+            Priority::Low => 1,
+        }
+    }
+
+    // Position of the class in Priority::ALL
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Snapshot of the scheduling activity of a class
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassMetrics {
+    pub scheduled: u64, // Times a task of the class was queued to run
+    pub polls: u64,     // Times a task of the class was polled
+    pub queued: u64,    // Tasks of the class waiting in the run queues right now
+    pub busy: Duration, // Time spent polling tasks of the class
+}
+
+// Counters of the scheduling activity of every class, updated by an executor
+#[derive(Default)]
+pub struct PriorityStats {
+    classes: [Counters; 3],
+}
+
+#[derive(Default)]
+struct Counters {
+    scheduled: AtomicU64,
+    polls: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+impl PriorityStats {
+    // Record a task of the class being queued to run
+    pub fn scheduled(&self, priority: Priority) {
+        let counters = &self.classes[priority.index()];
+        counters.scheduled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Record a poll of a task of the class, which took `elapsed`
+    pub fn polled(&self, priority: Priority, elapsed: Duration) {
+        let counters = &self.classes[priority.index()];
+        counters.polls.fetch_add(1, Ordering::Relaxed);
+        counters
+            .busy_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Get a snapshot of the counters of the class, with the number of its tasks
+    // the executor counted in its run queues
+    pub fn snapshot(&self, priority: Priority, queued: usize) -> ClassMetrics {
+        let counters = &self.classes[priority.index()];
+        let scheduled = counters.scheduled.load(Ordering::Relaxed);
+        let polls = counters.polls.load(Ordering::Relaxed);
+
+        ClassMetrics {
+            scheduled,
+            polls,
+            queued: queued as u64,
+            busy: Duration::from_nanos(counters.busy_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
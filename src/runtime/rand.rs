@@ -0,0 +1,56 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// Define a thread-local generator, seeded differently on every thread
+thread_local! {
+    static THREAD_RNG: Cell<FastRand> = Cell::new(FastRand::from_entropy());
+}
+
+// Struct representing a small xorshift64* generator, enough to spread choices
+// like the first branch of a fair select without pulling a dependency in
+#[derive(Clone, Copy, Debug)]
+pub struct FastRand {
+    state: u64,
+}
+
+impl FastRand {
+    // Create a generator from a seed, the same seed giving the same sequence
+    pub fn new(seed: u64) -> Self {
+        // Zero is the only state xorshift never leaves
+        FastRand {
+            state: if seed == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                seed
+            },
+        }
+    }
+
+    // Create a generator seeded from the randomness std uses for HashMap keys
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Get a number in 0..n, n must not be zero
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// Function to get a number in 0..n from the generator of the current thread
+pub fn below(n: usize) -> usize {
+    THREAD_RNG.with(|rng| {
+        let mut current = rng.get();
+        let value = current.below(n);
+        rng.set(current);
+        value
+    })
+}
@@ -1,66 +1,523 @@
-use crate::core::result::Result;
-use crate::runtime::polling::epoll;
+use crate::core::{error::IOError, os, result::Result};
+use crate::runtime::metrics::ReactorMetrics;
+use crate::runtime::polling::{self, Backend, Completion, Operation, Poller};
+use crate::runtime::ready::Ready;
+use crate::runtime::time::wheel::Wheel;
 
 use std::{
     cell::RefCell,
     collections::HashMap,
     os::fd::RawFd,
-    task::{Context, Waker},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
-// Define a thread-local variable to hold the Reactor instance
+// Define a thread-local variable to hold the Reactor instance. Worker threads of
+// the multi-threaded executor replace it with the Reactor shared by the pool.
 thread_local! {
-    pub static REACTOR: RefCell<Reactor> = RefCell::new(Reactor::new());
+    pub static REACTOR: RefCell<Arc<Reactor>> = RefCell::new(Arc::new(Reactor::new()));
+}
+
+// Struct representing the tasks waiting on a registered file descriptor. Readers
+// and writers are tracked separately so both directions can wait at the same time.
+#[derive(Default)]
+struct Registration {
+    reader: Option<Waker>, // Task waiting for the descriptor to become readable
+    writer: Option<Waker>, // Task waiting for the descriptor to become writable
+    readiness: Ready,      // Readiness observed since it was last cleared
+}
+
+impl Registration {
+    // Combined interest of the waiting tasks
+    fn interest(&self) -> i32 {
+        let mut interest = 0;
+        if self.reader.is_some() {
+            interest |= libc::EPOLLIN | libc::EPOLLRDHUP;
+        }
+        if self.writer.is_some() {
+            interest |= libc::EPOLLOUT;
+        }
+        interest
+    }
+}
+
+// Struct representing an operation submitted to a completion-based poller
+#[derive(Default)]
+struct Submission {
+    waker: Option<Waker>,           // Task waiting for the operation to complete
+    completion: Option<Completion>, // Outcome of the operation, once reported
+    abandoned: bool,                // Given up by its task, discarded once it completes
 }
 
 // Struct representing a Reactor for handling asynchronous I/O events
 pub struct Reactor {
-    poller: epoll::Poller,         // The underlying epoll-based event poller
-    wakers: HashMap<RawFd, Waker>, // A map to associate file descriptors with task wakers
+    poller: Box<dyn Poller>, // The underlying event poller (epoll or io_uring)
+    events: Mutex<polling::Events>, // Buffer receiving the events of a wait, reused across waits
+    registrations: Mutex<HashMap<RawFd, Registration>>, // Tasks waiting on each file descriptor
+    operations: Mutex<HashMap<u64, Submission>>, // Operations in flight by token
+    next_token: AtomicU64,   // Token of the next submitted operation
+    timers: Mutex<Wheel>,    // Timer wheel driving sleeps and intervals
+    waker_fd: RawFd,         // eventfd interrupting a blocked wait
+    notified: AtomicBool,    // Set once waker_fd was signalled, until a wait consumes it
+    parked: AtomicBool,      // Set while a thread is blocked waiting on the poller
+    disarmed: AtomicBool,    // Set when re-arming waker_fd failed, retried before the next wait
+    waits: AtomicU64,        // Waits on the poller so far
+    reported: AtomicU64,     // Events and completions reported by those waits
 }
 
 impl Reactor {
     // Constructor to create a new Reactor instance
     pub fn new() -> Self {
-        Self {
-            poller: epoll::Poller::new().unwrap(), // Initialize the epoll-based poller
-            wakers: HashMap::new(),                // Initialize the map for wakers
+        Self::with_capacity(1024)
+    }
+
+    // Constructor to create a Reactor handling at most `capacity` events per wait
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_default_backend(capacity).unwrap()
+    }
+
+    // Constructor to create a Reactor driven by the default backend. io_uring may
+    // be missing from the kernel or forbidden by a seccomp profile, epoll is
+    // used then.
+    pub fn with_default_backend(capacity: usize) -> Result<Self> {
+        Self::with_backend(Backend::default(), capacity).or_else(|err| match Backend::default() {
+            Backend::Epoll => Err(err),
+            _ => Self::with_backend(Backend::Epoll, capacity),
+        })
+    }
+
+    // Constructor to create a Reactor driven by the given backend, handling at
+    // most `capacity` events per wait
+    pub fn with_backend(backend: Backend, capacity: usize) -> Result<Self> {
+        let poller = polling::new_poller(backend)?; // Initialize the poller of the chosen backend
+
+        // Create the eventfd other threads signal to interrupt a blocked wait
+        let waker_fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if waker_fd == -1 {
+            return Err(IOError::SyscallResult(os::OS::err_msg()));
         }
+        if let Err(err) = poller.add(waker_fd, Self::flags(libc::EPOLLIN)) {
+            unsafe { libc::close(waker_fd) };
+            return Err(err);
+        }
+
+        Ok(Self {
+            poller,
+            events: Mutex::new(polling::Events::with_capacity(capacity)), // Initialize the event buffer
+            registrations: Mutex::new(HashMap::new()), // Initialize the map for registrations
+            operations: Mutex::new(HashMap::new()), // Initialize the map for submitted operations
+            next_token: AtomicU64::new(0),
+            timers: Mutex::new(Wheel::new()), // Initialize the timer wheel
+            waker_fd,
+            notified: AtomicBool::new(false),
+            parked: AtomicBool::new(false),
+            disarmed: AtomicBool::new(false),
+            waits: AtomicU64::new(0),
+            reported: AtomicU64::new(0),
+        })
+    }
+
+    // Function to wait for and retrieve I/O events from the poller, returning
+    // the wakers of the tasks waiting on them and of the expired timers
+    pub fn poll_wait(&self) -> Result<Vec<Waker>> {
+        self.poll_events(true)
+    }
+
+    // Function to retrieve the I/O events and expired timers available right
+    // now without blocking, for an executor kept busy by ready tasks
+    pub fn poll_now(&self) -> Result<Vec<Waker>> {
+        self.poll_events(false)
     }
 
-    // Function to wait for and retrieve I/O events from the poller
-    pub fn poll_wait(&mut self) -> Result<Vec<Waker>> {
-        let events = self.poller.wait()?; // Wait for events and get a list of event descriptors
+    fn poll_events(&self, block: bool) -> Result<Vec<Waker>> {
+        let mut events = self.events.lock().unwrap();
+
+        // Without waker_fd armed, unparks could not interrupt the wait
+        if self.disarmed.load(Ordering::Acquire) {
+            self.poller
+                .modify(self.waker_fd, Self::flags(libc::EPOLLIN))?;
+            self.disarmed.store(false, Ordering::Release);
+        }
+
+        // Block no longer than the next timer deadline. Parking under the timers
+        // lock lets add_timer tell whether it must interrupt the wait.
+        let timeout = if block {
+            let timers = self.timers.lock().unwrap();
+            self.parked.store(true, Ordering::SeqCst);
+            timers.next_timeout(Instant::now())
+        } else {
+            Some(Duration::ZERO)
+        };
+        let result = self.poller.wait(&mut events, timeout); // Wait for events and fill the buffer
+        self.parked.store(false, Ordering::SeqCst);
+        result?;
+
+        // From here on, a failure only affects the descriptor it happened on: the
+        // wakers taken out of the registrations must reach the executor
         let mut wakers: Vec<Waker> = Vec::new();
+        let mut reported = 0;
+
+        {
+            let mut registrations = self.registrations.lock().unwrap();
+            for event in events.iter() {
+                if event.key == self.waker_fd {
+                    if self.consume_notification().is_err() {
+                        self.disarmed.store(true, Ordering::Release);
+                    }
+                    continue;
+                }
+
+                reported += 1;
+                let Some(registration) = registrations.get_mut(&event.key) else {
+                    continue;
+                };
 
-        for event in events {
-            // Wake up corresponding task wakers associated with the event descriptor
-            if let Some(waker) = self.wakers.remove(&event.key) {
-                wakers.push(waker);
+                let ready = Self::readiness(&event);
+                registration.readiness |= ready;
+
+                // Wake the tasks waiting on a direction this event is relevant to
+                if !(ready & Ready::READ).is_empty() {
+                    wakers.extend(registration.reader.take());
+                }
+                if !(ready & Ready::WRITE).is_empty() {
+                    wakers.extend(registration.writer.take());
+                }
+
+                // The one-shot registration is now disarmed, re-arm it for the other direction
+                let interest = registration.interest();
+                if interest != 0
+                    && self
+                        .poller
+                        .modify(event.key, Self::flags(interest))
+                        .is_err()
+                {
+                    // The descriptor cannot report events anymore: wake the tasks still
+                    // waiting on it with error readiness, their next attempt gets the error
+                    registration.readiness |= Ready::ERROR;
+                    wakers.extend(registration.reader.take());
+                    wakers.extend(registration.writer.take());
+                }
             }
         }
 
+        {
+            // Hand the completed operations to the tasks that submitted them
+            let mut operations = self.operations.lock().unwrap();
+            for completion in events.drain_completions() {
+                reported += 1;
+                match operations.get_mut(&completion.token) {
+                    Some(submission) if submission.abandoned => {
+                        operations.remove(&completion.token);
+                        discard(completion);
+                    }
+                    Some(submission) => {
+                        submission.completion = Some(completion);
+                        wakers.extend(submission.waker.take());
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.reported.fetch_add(reported, Ordering::Relaxed);
+
+        wakers.extend(self.timers.lock().unwrap().process(Instant::now()));
         Ok(wakers)
     }
 
+    // Get a snapshot of the activity of the reactor
+    pub fn metrics(&self) -> ReactorMetrics {
+        ReactorMetrics {
+            waits: self.waits.load(Ordering::Relaxed),
+            events: self.reported.load(Ordering::Relaxed),
+            registered: self.registrations.lock().unwrap().len(),
+        }
+    }
+
+    // Function to interrupt a wait blocked on another thread, or make the next
+    // one return right away. Signals are coalesced until a wait consumes them.
+    pub fn unpark(&self) {
+        if !self.notified.swap(true, Ordering::AcqRel) {
+            let value: u64 = 1;
+            unsafe {
+                libc::write(
+                    self.waker_fd,
+                    &value as *const u64 as *const libc::c_void,
+                    8,
+                )
+            };
+        }
+    }
+
+    // Reset the eventfd once signalled and re-arm it for the next signal
+    fn consume_notification(&self) -> Result<()> {
+        let mut value: u64 = 0;
+        unsafe {
+            libc::read(
+                self.waker_fd,
+                &mut value as *mut u64 as *mut libc::c_void,
+                8,
+            )
+        };
+
+        // Unparks from now on must signal again. Cleared only after the read, so
+        // the write of an unpark racing with it cannot be drained while the flag
+        // stays set, which would make every later unpark skip its write.
+        self.notified.store(false, Ordering::Release);
+        self.poller
+            .modify(self.waker_fd, Self::flags(libc::EPOLLIN))
+    }
+
     // Function to register a file descriptor with specified events for polling
     pub fn register(&self, key: RawFd, events: i32) -> Result<()> {
-        self.poller
-            .add(key, (libc::EPOLLONESHOT | libc::EPOLLET | events) as u32)
+        self.registrations
+            .lock()
+            .unwrap()
+            .insert(key, Registration::default());
+        self.poller.add(key, Self::flags(events))
+    }
+
+    // Function to associate the task waker with the given directions (EPOLLIN for
+    // reads, EPOLLOUT for writes) of a registered file descriptor. The descriptor
+    // is re-armed for every direction a task is waiting on, not only these ones.
+    pub fn modify(&self, key: RawFd, events: i32, cx: &mut Context) -> Result<()> {
+        let mut registrations = self.registrations.lock().unwrap();
+        let registration = registrations.entry(key).or_default();
+        self.arm(key, registration, events, cx)
+    }
+
+    // Function to check whether a file descriptor is ready for reading. When it is
+    // not, the task waker is stored and the task is woken once it becomes ready.
+    pub fn poll_read_ready(&self, key: RawFd, cx: &mut Context) -> Poll<Result<Ready>> {
+        self.poll_ready(key, Ready::READ, libc::EPOLLIN, cx)
+    }
+
+    // Function to check whether a file descriptor is ready for writing. When it is
+    // not, the task waker is stored and the task is woken once it becomes ready.
+    pub fn poll_write_ready(&self, key: RawFd, cx: &mut Context) -> Poll<Result<Ready>> {
+        self.poll_ready(key, Ready::WRITE, libc::EPOLLOUT, cx)
+    }
+
+    // Function to forget readiness that turned out to be stale, typically after
+    // an operation returned WouldBlock
+    pub fn clear_readiness(&self, key: RawFd, ready: Ready) {
+        if let Some(registration) = self.registrations.lock().unwrap().get_mut(&key) {
+            registration.readiness = registration.readiness.remove(ready);
+        }
+    }
+
+    fn poll_ready(
+        &self,
+        key: RawFd,
+        mask: Ready,
+        events: i32,
+        cx: &mut Context,
+    ) -> Poll<Result<Ready>> {
+        let mut registrations = self.registrations.lock().unwrap();
+        let registration = registrations.entry(key).or_default();
+
+        let ready = registration.readiness & mask;
+        if !ready.is_empty() {
+            return Poll::Ready(Ok(ready));
+        }
+
+        match self.arm(key, registration, events, cx) {
+            Ok(()) => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
     }
 
-    // Function to modify the events for an already registered file descriptor
-    // and associate its task waker with the Reactor
-    pub fn modify(&mut self, key: RawFd, events: i32, cx: &mut Context) -> Result<()> {
+    // Store the task waker for the given directions and re-arm the descriptor.
+    // Called with the registrations locked so a concurrent poll_wait cannot
+    // re-arm it with a stale interest.
+    fn arm(
+        &self,
+        key: RawFd,
+        registration: &mut Registration,
+        events: i32,
+        cx: &mut Context,
+    ) -> Result<()> {
+        if events & libc::EPOLLIN != 0 {
+            registration.reader = Some(cx.waker().clone());
+        }
+        if events & libc::EPOLLOUT != 0 {
+            registration.writer = Some(cx.waker().clone());
+        }
+
         self.poller
-            .modify(key, (libc::EPOLLONESHOT | libc::EPOLLET | events) as u32)?;
-        self.wakers.insert(key, cx.waker().clone()); // Associate the waker with the file descriptor
-        Ok(())
+            .modify(key, Self::flags(registration.interest()))
+    }
+
+    // Convert a poller event to the readiness it signals
+    fn readiness(event: &polling::Event) -> Ready {
+        let mut ready = Ready::EMPTY;
+        if event.readable {
+            ready |= Ready::READABLE;
+        }
+        if event.writable {
+            ready |= Ready::WRITABLE;
+        }
+        if event.read_closed {
+            ready |= Ready::READ_CLOSED;
+        }
+        if event.hangup {
+            ready |= Ready::READ_CLOSED | Ready::WRITE_CLOSED;
+        }
+        if event.error {
+            ready |= Ready::ERROR;
+        }
+        ready
+    }
+
+    // Epoll flags used for every registration: one-shot, edge-triggered
+    fn flags(interest: i32) -> u32 {
+        (libc::EPOLLONESHOT | libc::EPOLLET | interest) as u32
+    }
+
+    // Function to check whether the poller performs reads, writes and accepts
+    // itself, in which case they are submitted instead of tried directly
+    pub fn completion_based(&self) -> bool {
+        self.poller.supports_operations()
+    }
+
+    // Function to submit an operation to the poller, returning its token. The
+    // task waker is stored and the task is woken once the operation completes.
+    pub fn submit(&self, op: Operation, cx: &mut Context) -> Result<u64> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+
+        // Insert the entry first so a completion reported right away is not lost
+        self.operations.lock().unwrap().insert(
+            token,
+            Submission {
+                waker: Some(cx.waker().clone()),
+                completion: None,
+                abandoned: false,
+            },
+        );
+
+        if let Err(err) = self.poller.submit(token, op) {
+            self.operations.lock().unwrap().remove(&token);
+            return Err(err);
+        }
+
+        Ok(token)
+    }
+
+    // Function to check whether a submitted operation has completed, refreshing
+    // the task waker otherwise
+    pub fn poll_operation(&self, token: u64, cx: &mut Context) -> Poll<Completion> {
+        let mut operations = self.operations.lock().unwrap();
+        let submission = operations.entry(token).or_default();
+
+        match submission.completion.take() {
+            Some(completion) => {
+                operations.remove(&token);
+                Poll::Ready(completion)
+            }
+            None => {
+                submission.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    // Function to ask the poller to cancel a submitted operation, still waiting
+    // for its completion: -ECANCELED if the cancellation won, its outcome otherwise
+    pub fn interrupt_operation(&self, token: u64) {
+        let in_flight = matches!(
+            self.operations.lock().unwrap().get(&token),
+            Some(Submission {
+                completion: None,
+                ..
+            })
+        );
+        if in_flight {
+            self.poller.cancel(token);
+        }
+    }
+
+    // Function to give up on a submitted operation. The entry is kept until the
+    // completion arrives, which is then discarded, closing an accepted connection.
+    pub fn cancel_operation(&self, token: u64) {
+        let completion = {
+            let mut operations = self.operations.lock().unwrap();
+            let Some(submission) = operations.get_mut(&token) else {
+                return;
+            };
+
+            match submission.completion.take() {
+                Some(completion) => {
+                    operations.remove(&token);
+                    completion
+                }
+                None => {
+                    submission.abandoned = true;
+                    submission.waker = None;
+                    drop(operations);
+                    self.poller.cancel(token);
+                    return;
+                }
+            }
+        };
+        discard(completion);
+    }
+
+    // Function to register a timer firing at the given deadline, returning its key
+    pub fn add_timer(&self, deadline: Instant, cx: &mut Context) -> u64 {
+        let mut timers = self.timers.lock().unwrap();
+
+        // A thread blocked with a later timeout must wake up for this timer
+        let now = Instant::now();
+        let earlier = timers
+            .next_timeout(now)
+            .is_none_or(|timeout| deadline < now + timeout);
+        let key = timers.insert(deadline, cx.waker().clone());
+
+        if earlier && self.parked.load(Ordering::SeqCst) {
+            self.unpark();
+        }
+        key
     }
 
-    // Function to remove a file descriptor and its associated task waker from the Reactor
-    pub fn remove(&mut self, key: RawFd) {
-        self.wakers.remove(&key); // Remove the waker associated with the file descriptor
+    // Function to refresh the task waker of a registered timer
+    pub fn update_timer(&self, key: u64, cx: &mut Context) {
+        self.timers.lock().unwrap().update(key, cx.waker());
+    }
+
+    // Function to cancel a registered timer
+    pub fn remove_timer(&self, key: u64) {
+        self.timers.lock().unwrap().remove(key);
+    }
+
+    // Function to remove a file descriptor and its associated task wakers from the Reactor
+    pub fn remove(&self, key: RawFd) {
+        self.registrations.lock().unwrap().remove(&key); // Remove the wakers associated with the file descriptor
         self.poller.delete(key); // Delete the file descriptor from the poller
     }
 }
+
+// Function to release what the completion of an abandoned operation holds: the
+// connection an accept returned is closed, nobody is left to use it
+fn discard(completion: Completion) {
+    if let (Operation::Accept { .. }, fd) = (&completion.operation, completion.result) {
+        if fd >= 0 {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+impl Drop for Reactor {
+    // Close the eventfd when the Reactor is dropped
+    fn drop(&mut self) {
+        unsafe { libc::close(self.waker_fd) };
+    }
+}
+
+impl Default for Reactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,82 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
+// Struct representing a set of readiness flags reported by the reactor for a file descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ready(u8);
+
+impl Ready {
+    pub const EMPTY: Ready = Ready(0);
+    pub const READABLE: Ready = Ready(1 << 0); // Data can be read without blocking
+    pub const WRITABLE: Ready = Ready(1 << 1); // Data can be written without blocking
+    pub const READ_CLOSED: Ready = Ready(1 << 2); // Peer shut down its writing side (EPOLLRDHUP/EPOLLHUP)
+    pub const WRITE_CLOSED: Ready = Ready(1 << 3); // Connection hung up, writes will fail (EPOLLHUP)
+    pub const ERROR: Ready = Ready(1 << 4); // An error is pending on the socket (EPOLLERR)
+
+    // Readiness a reader waits on: data, end of stream or error
+    pub const READ: Ready = Ready(Self::READABLE.0 | Self::READ_CLOSED.0 | Self::ERROR.0);
+
+    // Readiness a writer waits on: buffer space, hangup or error
+    pub const WRITE: Ready = Ready(Self::WRITABLE.0 | Self::WRITE_CLOSED.0 | Self::ERROR.0);
+
+    // Check whether no flag is set
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    // Check whether every flag of other is set
+    pub fn contains(self, other: Ready) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // Check whether the descriptor is readable
+    pub fn is_readable(self) -> bool {
+        self.contains(Ready::READABLE)
+    }
+
+    // Check whether the descriptor is writable
+    pub fn is_writable(self) -> bool {
+        self.contains(Ready::WRITABLE)
+    }
+
+    // Check whether the peer closed its writing side
+    pub fn is_read_closed(self) -> bool {
+        self.contains(Ready::READ_CLOSED)
+    }
+
+    // Check whether the connection hung up
+    pub fn is_write_closed(self) -> bool {
+        self.contains(Ready::WRITE_CLOSED)
+    }
+
+    // Check whether an error is pending on the descriptor
+    pub fn is_error(self) -> bool {
+        self.contains(Ready::ERROR)
+    }
+
+    // Return this set without the flags of other
+    pub fn remove(self, other: Ready) -> Ready {
+        Ready(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Ready {
+    type Output = Ready;
+
+    fn bitor(self, other: Ready) -> Ready {
+        Ready(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Ready {
+    fn bitor_assign(&mut self, other: Ready) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for Ready {
+    type Output = Ready;
+
+    fn bitand(self, other: Ready) -> Ready {
+        Ready(self.0 & other.0)
+    }
+}
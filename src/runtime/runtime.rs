@@ -0,0 +1,213 @@
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use super::builder::Builder;
+use super::join_handle::JoinHandle;
+use super::metrics::RuntimeMetrics;
+use super::multi_thread::MultiThread;
+use super::priority::{ClassMetrics, Priority};
+use super::shutdown::ShutdownReport;
+use super::task::RemainingTasks;
+use crate::core::error::{IOError, JoinError};
+use crate::core::result::Result;
+
+// Notifications received by a thread blocked on the runtime
+enum Signal {
+    Woken,              // The future the thread waits for may have progressed
+    Exited(Result<()>), // A worker exited with the given result
+}
+
+// Waker of a thread blocked on the runtime
+struct SignalWaker(mpsc::Sender<Signal>);
+
+impl Wake for SignalWaker {
+    fn wake(self: Arc<Self>) {
+        let _ = self.0.send(Signal::Woken);
+    }
+}
+
+// Struct representing a configured runtime: worker threads sharing a reactor
+// and a blocking pool, started as soon as the runtime is built
+pub struct Runtime {
+    pool: MultiThread,
+    remaining: RemainingTasks, // What to do with the tasks left once block_on completes
+    sender: mpsc::Sender<Signal>,
+    signals: Mutex<mpsc::Receiver<Signal>>, // Wakeups and worker exits
+}
+
+impl Runtime {
+    // Constructor to create a runtime with the default settings
+    pub fn new() -> Result<Runtime> {
+        Builder::new().build()
+    }
+
+    // Constructor to create a runtime from a pool, starting its workers
+    pub fn start(pool: MultiThread, remaining: RemainingTasks) -> Result<Runtime> {
+        let (sender, receiver) = mpsc::channel();
+
+        let exits = sender.clone();
+        pool.start(Arc::new(move |result| {
+            let _ = exits.send(Signal::Exited(result));
+        }))?;
+
+        Ok(Runtime {
+            pool,
+            remaining,
+            sender,
+            signals: Mutex::new(receiver),
+        })
+    }
+
+    // Function to spawn a Future onto the runtime
+    pub fn spawn<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.pool.spawn(f)
+    }
+
+    // Function to spawn a Future onto the runtime in the given scheduling class
+    pub fn spawn_with_priority<F>(&self, priority: Priority, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.pool.spawn_with_priority(priority, f)
+    }
+
+    // Get the scheduling activity of a class, over every worker
+    pub fn class_metrics(&self, priority: Priority) -> ClassMetrics {
+        self.pool.class_metrics(priority)
+    }
+
+    // Get a snapshot of the activity of the runtime: tasks spawned, completed
+    // and alive, run queue depths, busy and parked time of every worker, reactor
+    // events per wait, registered descriptors and poll time histograms
+    pub fn metrics(&self) -> RuntimeMetrics {
+        self.pool.metrics()
+    }
+
+    // Function to run the Future on the runtime, blocking the current thread
+    // until it completes and returning its output. Spawned tasks still running
+    // are handled as configured with Builder::remaining_tasks.
+    pub fn block_on<F>(&self, f: F) -> Result<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let mut root = self.pool.spawn(f);
+        let output = self.wait(|cx| Pin::new(&mut root).poll(cx))?;
+
+        match self.remaining {
+            RemainingTasks::Detach => {}
+            RemainingTasks::Cancel => {
+                self.pool.tasks().cancel_all();
+            }
+            RemainingTasks::Wait => self.wait(|cx| self.pool.tasks().poll_empty(cx))?,
+        }
+
+        match output {
+            Ok(output) => Ok(output),
+            Err(JoinError::Panicked(msg)) => panic::resume_unwind(Box::new(msg)),
+            // Cancelled along with the other tasks, e.g. by a nested block_on
+            // set to cancel the tasks left once it completes
+            Err(JoinError::Cancelled) => Err(IOError::SyscallResult(
+                "the block_on future was cancelled".to_string(),
+            )),
+        }
+    }
+
+    // Function to drain the runtime once its work should stop, e.g. after a
+    // CancellationToken ended the accept loop: the tasks still running get up
+    // to `timeout` to complete, then the rest is cancelled
+    pub fn drain(&self, timeout: Duration) -> Result<ShutdownReport> {
+        let tasks = self.pool.tasks();
+        let completed = tasks.completed();
+
+        let deadline = Instant::now() + timeout;
+        let aborted = match self.wait_until(Some(deadline), |cx| tasks.poll_empty(cx))? {
+            Some(()) => 0,
+            None => tasks.cancel_all(),
+        };
+
+        Ok(ShutdownReport {
+            drained: tasks.completed() - completed,
+            aborted,
+        })
+    }
+
+    // Block the current thread until `poll` is ready, failing if the workers exit meanwhile
+    fn wait<T>(&self, poll: impl FnMut(&mut Context<'_>) -> Poll<T>) -> Result<T> {
+        Ok(self
+            .wait_until(None, poll)?
+            .expect("a wait without deadline cannot time out"))
+    }
+
+    // Block the current thread until `poll` is ready or the deadline is reached,
+    // in which case None is returned
+    fn wait_until<T>(
+        &self,
+        deadline: Option<Instant>,
+        mut poll: impl FnMut(&mut Context<'_>) -> Poll<T>,
+    ) -> Result<Option<T>> {
+        let waker = Waker::from(Arc::new(SignalWaker(self.sender.clone())));
+        let mut cx = Context::from_waker(&waker);
+        let signals = self.signals.lock().unwrap();
+
+        loop {
+            if let Poll::Ready(output) = poll(&mut cx) {
+                return Ok(Some(output));
+            }
+
+            let signal = match deadline {
+                Some(deadline) => {
+                    match signals.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Err(mpsc::RecvTimeoutError::Timeout) => return Ok(None),
+                        signal => signal.map_err(|_| mpsc::RecvError),
+                    }
+                }
+                None => signals.recv(),
+            };
+
+            match signal {
+                Ok(Signal::Woken) => continue,
+                Ok(Signal::Exited(Err(err))) => return Err(err),
+                Ok(Signal::Exited(Ok(()))) | Err(_) => {
+                    return Err(IOError::SyscallResult(
+                        "the runtime was shut down".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    // Function to shut the runtime down, waiting at most `timeout` for the
+    // workers to finish the task they are running
+    pub fn shutdown_timeout(self, timeout: Duration) {
+        self.pool.shutdown();
+
+        let deadline = Instant::now() + timeout;
+        let signals = self.signals.lock().unwrap();
+        let mut exited = 0;
+
+        while exited < self.pool.workers() {
+            match signals.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Signal::Exited(_)) => exited += 1,
+                Ok(Signal::Woken) => continue,
+                Err(_) => break, // Deadline reached
+            }
+        }
+    }
+}
+
+impl Drop for Runtime {
+    // Stop the workers without waiting for them when the runtime is dropped
+    fn drop(&mut self) {
+        self.pool.shutdown();
+    }
+}
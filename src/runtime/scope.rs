@@ -0,0 +1,231 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::executor;
+use super::join_handle::JoinHandle;
+use super::shutdown::CancellationToken;
+
+// Function to open a scope (nursery) for child tasks:
+//
+//     scope(|s| async move {
+//         s.spawn(async { Ok(()) });
+//         Ok(())
+//     })
+//     .await
+//
+// The body gets a Scope to spawn children with, and the returned future only
+// completes once the body and every child finished or were cancelled. The first
+// error of the body or of a child cancels the others and is returned; a child
+// panic cancels the others too and is re-raised once they are gone.
+pub fn scope<B, Fut, T, E>(body: B) -> ScopeFuture<Fut, T, E>
+where
+    B: FnOnce(Scope<E>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let scope = Scope {
+        shared: Arc::new(Shared {
+            state: Mutex::new(State {
+                active: 0,
+                failure: None,
+                waker: None,
+            }),
+            token: CancellationToken::new(),
+        }),
+    };
+
+    ScopeFuture {
+        body: Some(Box::pin(body(scope.clone()))),
+        output: None,
+        scope,
+    }
+}
+
+// Handle to spawn child tasks in a scope. Clones spawn into the same scope, so
+// children can spawn siblings.
+pub struct Scope<E> {
+    shared: Arc<Shared<E>>,
+}
+
+struct Shared<E> {
+    state: Mutex<State<E>>,
+    token: CancellationToken, // Cancelled on the first failure, or once the scope completed
+}
+
+struct State<E> {
+    active: usize,               // Children not finished or cancelled yet
+    failure: Option<Failure<E>>, // First error or panic of the scope
+    waker: Option<Waker>,        // Waker of the task awaiting the scope
+}
+
+// Reason for a scope to cancel its children
+enum Failure<E> {
+    Error(E),
+    Panic(Box<dyn Any + Send>),
+}
+
+impl<E> Clone for Scope<E> {
+    fn clone(&self) -> Self {
+        Scope {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<E: Send + 'static> Scope<E> {
+    // Function to spawn a child task in the scope, returning a handle to await
+    // its output. The handle resolves to None if the child failed or was
+    // cancelled, its error goes to the scope. Children spawned once the scope
+    // failed or completed are cancelled right away.
+    pub fn spawn<F, T>(&self, f: F) -> JoinHandle<Option<T>>
+    where
+        F: Future<Output = Result<T, E>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.shared.state.lock().unwrap().active += 1;
+
+        let child = Child {
+            future: Box::pin(f),
+            shared: self.shared.clone(),
+        };
+        let token = self.shared.token.clone();
+        executor::spawn(async move { token.run_until_cancelled(child).await.flatten() })
+    }
+}
+
+impl<E> Scope<E> {
+    // Check whether the scope stopped its children, after a failure or because it completed
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.token.is_cancelled()
+    }
+}
+
+impl<E> Shared<E> {
+    // Record the failure if it is the first one, then cancel the children and
+    // wake the task awaiting the scope so it stops the body
+    fn fail(&self, failure: Failure<E>) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.failure.get_or_insert(failure);
+            state.waker.take()
+        };
+
+        self.token.cancel();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// Future of a child task, reporting its failure to the scope and its end,
+// however it happens, to the task awaiting the scope
+struct Child<F, E> {
+    future: Pin<Box<F>>,
+    shared: Arc<Shared<E>>,
+}
+
+impl<F, T, E> Future for Child<F, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let future = self.future.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(Ok(output))) => Poll::Ready(Some(output)),
+            Ok(Poll::Ready(Err(err))) => {
+                self.shared.fail(Failure::Error(err));
+                Poll::Ready(None)
+            }
+            Err(payload) => {
+                self.shared.fail(Failure::Panic(payload));
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<F, E> Drop for Child<F, E> {
+    // Completed, cancelled or dropped with the runtime, the child is gone
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.active -= 1;
+            if state.active > 0 {
+                return;
+            }
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// Future running the body of a scope and waiting for its children. Dropping it
+// cancels the children, which stop at their next poll.
+pub struct ScopeFuture<Fut, T, E> {
+    body: Option<Pin<Box<Fut>>>, // Body of the scope, until it completes or is cancelled
+    output: Option<T>,           // Output of the body, once it succeeded
+    scope: Scope<E>,
+}
+
+impl<Fut, T, E> Unpin for ScopeFuture<Fut, T, E> {}
+
+impl<Fut, T, E> Future for ScopeFuture<Fut, T, E>
+where
+    Fut: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T, E>> {
+        let state = self.get_mut();
+        let shared = &state.scope.shared;
+
+        if let Some(body) = state.body.as_mut() {
+            if shared.token.is_cancelled() {
+                // A child failed, the body is cancelled like its siblings
+                state.body = None;
+            } else if let Poll::Ready(result) = body.as_mut().poll(cx) {
+                state.body = None;
+                match result {
+                    Ok(output) => state.output = Some(output),
+                    Err(err) => shared.fail(Failure::Error(err)),
+                }
+            }
+        }
+
+        let failure = {
+            let mut scope = shared.state.lock().unwrap();
+            if state.body.is_some() || scope.active > 0 {
+                scope.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            scope.failure.take()
+        };
+
+        // Children spawned from now on are cancelled right away
+        shared.token.cancel();
+
+        match failure {
+            Some(Failure::Error(err)) => Poll::Ready(Err(err)),
+            Some(Failure::Panic(payload)) => panic::resume_unwind(payload),
+            None => Poll::Ready(Ok(state
+                .output
+                .take()
+                .expect("ScopeFuture polled after completion"))),
+        }
+    }
+}
+
+impl<Fut, T, E> Drop for ScopeFuture<Fut, T, E> {
+    fn drop(&mut self) {
+        self.scope.shared.token.cancel();
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+// Struct representing a token telling tasks to stop what they are doing, e.g.
+// an accept loop. Clones share the same state, cancelling one cancels them all.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    waiters: Mutex<HashMap<u64, Waker>>, // Futures waiting for the cancellation, by key
+    next_key: AtomicU64,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Function to cancel the token, waking every task waiting on it
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let waiters: Vec<Waker> = {
+            let mut waiters = self.inner.waiters.lock().unwrap();
+            waiters.drain().map(|(_, waker)| waker).collect()
+        };
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    // Function to get a future completing once the token is cancelled
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+            key: None,
+        }
+    }
+
+    // Function to run a future until it completes or the token is cancelled,
+    // whichever comes first. The future is dropped when cancelled, which
+    // cancels the operation it was waiting for.
+    pub fn run_until_cancelled<F: Future>(&self, future: F) -> RunUntilCancelled<F> {
+        RunUntilCancelled {
+            future: Box::pin(future),
+            cancelled: self.cancelled(),
+        }
+    }
+}
+
+// Future completing once its token is cancelled
+pub struct Cancelled {
+    token: CancellationToken,
+    key: Option<u64>, // Key of the registered waker, if any
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let state = self.get_mut();
+        let inner = &state.token.inner;
+
+        if inner.cancelled.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        let mut waiters = inner.waiters.lock().unwrap();
+
+        // The token may have been cancelled while we were taking the lock
+        if inner.cancelled.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        // Register the waker once and refresh it on later polls, so polling in
+        // a loop does not grow the waiter list
+        let key = *state
+            .key
+            .get_or_insert_with(|| inner.next_key.fetch_add(1, Ordering::Relaxed));
+        waiters.insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled {
+    // Unregister the waker of a future dropped before the cancellation
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.inner.waiters.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+// Future for racing a future against the cancellation of a token. Resolves to
+// None if the token was cancelled first.
+pub struct RunUntilCancelled<F> {
+    future: Pin<Box<F>>,
+    cancelled: Cancelled,
+}
+
+impl<F: Future> Future for RunUntilCancelled<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        // Check the token first so a cancelled loop stops even if work is ready
+        if Pin::new(&mut state.cancelled).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+
+        state.future.as_mut().poll(cx).map(Some)
+    }
+}
+
+// Outcome of a graceful shutdown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub drained: usize, // Tasks that completed before the deadline
+    pub aborted: usize, // Tasks cancelled once the deadline was reached
+}
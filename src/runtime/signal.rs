@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem;
+use std::os::fd::RawFd;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::core::{error::IOError, os, result::Result};
+use crate::runtime::reactor::{Reactor, REACTOR};
+use crate::runtime::ready::Ready;
+
+// Signals that can be received asynchronously
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+    Interrupt, // SIGINT, sent by Ctrl-C
+    Terminate, // SIGTERM, sent by kill and service managers
+    Hangup,    // SIGHUP, conventionally asks a daemon to reload its configuration
+    Quit,      // SIGQUIT
+    User1,     // SIGUSR1
+    User2,     // SIGUSR2
+}
+
+impl SignalKind {
+    // Get the signal number
+    pub fn as_raw(self) -> i32 {
+        match self {
+            SignalKind::Interrupt => libc::SIGINT,
+            SignalKind::Terminate => libc::SIGTERM,
+            SignalKind::Hangup => libc::SIGHUP,
+            SignalKind::Quit => libc::SIGQUIT,
+            SignalKind::User1 => libc::SIGUSR1,
+            SignalKind::User2 => libc::SIGUSR2,
+        }
+    }
+}
+
+// Function to block the signals in the calling thread, so they are left pending
+// for the signalfd instead of running their default action. Threads inherit the
+// mask of the thread creating them: call it from main before building the
+// runtime, otherwise a thread started earlier may still receive the signals.
+pub fn block(kinds: &[SignalKind]) -> Result<()> {
+    unsafe {
+        let mut mask: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        for kind in kinds {
+            libc::sigaddset(&mut mask, kind.as_raw());
+        }
+
+        // pthread_sigmask returns the error instead of setting errno
+        let result = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut());
+        if result != 0 {
+            return Err(IOError::SyscallResult(os::OS::strerror(result)));
+        }
+    }
+    Ok(())
+}
+
+// Function to start listening for a signal. The signal is blocked in the
+// calling thread (see block) and every stream created for it receives it.
+pub fn signal(kind: SignalKind) -> Result<Signal> {
+    block(&[kind])?;
+
+    let mut driver = DRIVER.lock().unwrap();
+    if driver.is_none() {
+        *driver = Some(Driver::new()?);
+    }
+    let driver = driver.as_mut().unwrap();
+
+    driver.listen(kind.as_raw())?;
+
+    let key = driver.next_key;
+    driver.next_key += 1;
+    driver.listeners.insert(
+        key,
+        Listener {
+            signo: kind.as_raw(),
+            pending: false,
+            waker: None,
+        },
+    );
+
+    Ok(Signal { kind, key })
+}
+
+// The signalfd shared by every stream, created on first use and closed once
+// the last stream is dropped
+static DRIVER: Mutex<Option<Driver>> = Mutex::new(None);
+
+// Struct reading the signalfd and dispatching the signals to the streams
+struct Driver {
+    fd: RawFd,                         // The signalfd, non-blocking
+    mask: libc::sigset_t,              // Signals the signalfd accepts
+    reactor: Option<Arc<Reactor>>,     // Reactor the signalfd is registered with, if any yet
+    waker: Waker,                      // Wakes every waiting stream once the signalfd is readable
+    listeners: HashMap<u64, Listener>, // Streams by key
+    next_key: u64,
+}
+
+// State of a signal stream
+struct Listener {
+    signo: i32,
+    pending: bool,        // Received since the last recv, repeated signals coalesce
+    waker: Option<Waker>, // Task waiting in recv
+}
+
+impl Driver {
+    fn new() -> Result<Driver> {
+        let mask = unsafe {
+            let mut mask: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut mask);
+            mask
+        };
+
+        let fd = unsafe { libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(IOError::SyscallResult(os::OS::err_msg()));
+        }
+
+        Ok(Driver {
+            fd,
+            mask,
+            reactor: None,
+            waker: Waker::from(Arc::new(Dispatch)),
+            listeners: HashMap::new(),
+            next_key: 0,
+        })
+    }
+
+    // Function to register the signalfd with the reactor of the current thread,
+    // moving it from the one it was registered with before, if any. Streams
+    // may be received from tasks running on different runtimes.
+    fn attach(&mut self) -> Result<()> {
+        let reactor = REACTOR.with(|current| current.borrow().clone());
+        if let Some(previous) = &self.reactor {
+            if Arc::ptr_eq(previous, &reactor) {
+                return Ok(());
+            }
+            previous.remove(self.fd);
+            self.reactor = None;
+        }
+
+        reactor.register(self.fd, libc::EPOLLIN)?;
+        self.reactor = Some(reactor);
+        Ok(())
+    }
+
+    // Add a signal to the ones the signalfd accepts
+    fn listen(&mut self, signo: i32) -> Result<()> {
+        if unsafe { libc::sigismember(&self.mask, signo) } == 1 {
+            return Ok(());
+        }
+
+        unsafe { libc::sigaddset(&mut self.mask, signo) };
+        let fd = unsafe { libc::signalfd(self.fd, &self.mask, 0) };
+        if fd == -1 {
+            return Err(IOError::SyscallResult(os::OS::err_msg()));
+        }
+        Ok(())
+    }
+
+    // Read every pending signal and mark the streams listening for them
+    fn dispatch(&mut self) -> Result<()> {
+        loop {
+            let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+            let size = mem::size_of::<libc::signalfd_siginfo>();
+            let read = unsafe {
+                libc::read(
+                    self.fd,
+                    &mut info as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                    size,
+                )
+            };
+
+            if read == -1 {
+                return match os::OS::err_no() {
+                    libc::EAGAIN => {
+                        if let Some(reactor) = &self.reactor {
+                            reactor.clear_readiness(self.fd, Ready::READABLE);
+                        }
+                        Ok(())
+                    }
+                    libc::EINTR => continue,
+                    _ => Err(IOError::SyscallResult(os::OS::err_msg())),
+                };
+            }
+
+            for listener in self.listeners.values_mut() {
+                if listener.signo == info.ssi_signo as i32 {
+                    listener.pending = true;
+                    if let Some(waker) = listener.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Drop implementation to remove the signalfd from its reactor and close it
+impl Drop for Driver {
+    fn drop(&mut self) {
+        if let Some(reactor) = self.reactor.take() {
+            reactor.remove(self.fd);
+        }
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// Waker armed on the signalfd: wakes every waiting stream, the first one polled
+// reads the signals on behalf of the others
+struct Dispatch;
+
+impl Wake for Dispatch {
+    fn wake(self: Arc<Self>) {
+        let wakers: Vec<Waker> = match DRIVER.lock().unwrap().as_mut() {
+            Some(driver) => driver
+                .listeners
+                .values_mut()
+                .filter_map(|listener| listener.waker.take())
+                .collect(),
+            None => return,
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+// Struct representing a stream of deliveries of a signal
+pub struct Signal {
+    kind: SignalKind,
+    key: u64, // Key of the listener in the driver
+}
+
+impl Signal {
+    // Get the signal the stream receives
+    pub fn kind(&self) -> SignalKind {
+        self.kind
+    }
+
+    // Function to wait for the next delivery of the signal. Deliveries that
+    // happen before recv is called again are coalesced into one.
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { signal: self }
+    }
+}
+
+impl Drop for Signal {
+    // Stop dispatching to the stream, tearing the driver down after the last
+    // one. The signal stays blocked.
+    fn drop(&mut self) {
+        let unused = {
+            let mut driver = DRIVER.lock().unwrap();
+            match driver.as_mut() {
+                Some(current) => {
+                    current.listeners.remove(&self.key);
+                    if current.listeners.is_empty() {
+                        driver.take()
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            }
+        };
+
+        // Dropped once the lock is released, removing it from the reactor
+        // drops the dispatching waker
+        drop(unused);
+    }
+}
+
+// Future waiting for the next delivery of a signal
+pub struct Recv<'a> {
+    signal: &'a mut Signal,
+}
+
+impl Future for Recv<'_> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let key = self.signal.key;
+        let mut driver = DRIVER.lock().unwrap();
+        let driver = driver.as_mut().unwrap();
+
+        // Follow the task to the reactor it runs on
+        if let Err(err) = driver.attach() {
+            return Poll::Ready(Err(err));
+        }
+
+        loop {
+            if let Err(err) = driver.dispatch() {
+                return Poll::Ready(Err(err));
+            }
+
+            let listener = driver.listeners.get_mut(&key).unwrap();
+            if listener.pending {
+                listener.pending = false;
+                return Poll::Ready(Ok(()));
+            }
+            listener.waker = Some(cx.waker().clone());
+
+            // Arm the signalfd with the dispatching waker, so the streams keep
+            // being woken whichever of them is dropped
+            let mut dispatch = Context::from_waker(&driver.waker);
+            let reactor = driver.reactor.as_ref().unwrap();
+            match reactor.poll_read_ready(driver.fd, &mut dispatch) {
+                Poll::Ready(Ok(_)) => continue, // Became ready meanwhile, read again
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
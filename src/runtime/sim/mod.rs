@@ -0,0 +1,495 @@
+pub mod net;
+pub mod time;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use super::join_handle::{self, JoinHandle, LocalFuture};
+use super::rand::FastRand;
+use crate::core::error::{JoinError, SimError};
+
+pub use net::{TcpListener, TcpStream};
+pub use time::{now, sleep, sleep_until, timeout};
+
+// Environment variable a test run reads its seed from, to replay a failure
+pub const SEED_VAR: &str = "SIM_SEED";
+
+// Default number of task polls and timer firings after which a simulation gives up
+pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+// Define a thread-local variable holding the simulation running on the current
+// thread, used by the simulated time and network
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<World>>> = const { RefCell::new(None) };
+}
+
+// Struct representing the settings of a simulation. Runs with the same seed
+// and settings go through the exact same interleaving of tasks, network delays
+// and resets, as long as the futures stay off the parts the simulation does
+// not cover, listed on Simulation.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    seed: u64,
+    min_latency: Duration, // Shortest delay of a segment on the simulated network
+    max_latency: Duration, // Longest delay of a segment on the simulated network
+    reset_rate: f64,       // Chance of every write to reset its connection instead
+    step_limit: u64,       // Steps after which the simulation fails with StepLimit
+}
+
+impl Builder {
+    // Constructor to create the default settings, with the seed from SIM_SEED
+    // if set, or a random one otherwise
+    pub fn new() -> Self {
+        let seed = env_seed().unwrap_or_else(|| FastRand::from_entropy().next_u64());
+
+        Builder {
+            seed,
+            min_latency: Duration::from_micros(100),
+            max_latency: Duration::from_millis(10),
+            reset_rate: 0.0,
+            step_limit: DEFAULT_STEP_LIMIT,
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Set the range the delay of every segment sent on the network is drawn from
+    pub fn latency(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "the minimum latency exceeds the maximum");
+        self.min_latency = min;
+        self.max_latency = max;
+        self
+    }
+
+    // Set the chance, from 0 to 1, of every write to reset its connection
+    pub fn reset_rate(mut self, rate: f64) -> Self {
+        self.reset_rate = rate;
+        self
+    }
+
+    pub fn step_limit(mut self, steps: u64) -> Self {
+        self.step_limit = steps;
+        self
+    }
+
+    pub fn build(&self) -> Simulation {
+        Simulation {
+            config: self.clone(),
+        }
+    }
+
+    // Function to run the test under `runs` simulations, or only the one of
+    // SIM_SEED if set, whatever the seed of the builder. The first run uses
+    // the seed of the builder, the next ones seeds drawn from it. Panics on the
+    // first failure, with the seed to set in SIM_SEED to replay it.
+    pub fn check<F, Fut>(&self, runs: u64, test: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let run = |seed: u64| {
+            if let Err(err) = self.clone().seed(seed).build().block_on(test()) {
+                panic!("{err} Replay it with {SEED_VAR}={}", err.seed());
+            }
+        };
+
+        if let Some(seed) = env_seed() {
+            return run(seed);
+        }
+
+        let mut seeds = FastRand::new(self.seed);
+        let mut seed = self.seed;
+        for _ in 0..runs {
+            run(seed);
+            seed = seeds.next_u64();
+        }
+    }
+}
+
+// Get the seed set in SIM_SEED, if any
+fn env_seed() -> Option<u64> {
+    env::var(SEED_VAR).ok().and_then(|seed| seed.parse().ok())
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Struct representing a deterministic runtime for tests: a single-threaded
+// executor picking the next task to poll at random from the seed, a virtual
+// clock jumping to the next timer whenever no task is ready, and an in-memory
+// network with seed-driven delays and resets.
+//
+// While a simulation runs, the runtime routes to it: executor::spawn and
+// spawn_local spawn onto it, runtime::time sleeps on the virtual clock, and
+// runtime::net listeners and streams use the simulated network, so handlers
+// written against the runtime replay the same way. Not simulated: raw file
+// descriptors, spawn_blocking and signals, which use real threads and the OS.
+pub struct Simulation {
+    config: Builder,
+}
+
+// State of a running simulation
+struct World {
+    seed: u64,
+    rng: RefCell<FastRand>,
+    config: Builder,
+    epoch: Instant,        // Instant standing for the start of the virtual clock
+    clock: Cell<Duration>, // Virtual time since the simulation started
+    timers: RefCell<BTreeMap<(Duration, u64), Waker>>, // Wakers by deadline, then registration order
+    next_timer: Cell<u64>,
+    tasks: RefCell<BTreeMap<usize, LocalFuture>>, // Tasks not completed yet, by id
+    next_id: Cell<usize>,
+    ready: Arc<Mutex<BTreeSet<usize>>>, // Tasks woken, polled in a seed-driven order
+    network: RefCell<net::Network>,
+}
+
+// Waker of a simulated task, marking it ready
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.lock().unwrap().insert(self.id);
+    }
+}
+
+impl Simulation {
+    pub fn seed(&self) -> u64 {
+        self.config.seed
+    }
+
+    // Function to run the future to completion on the simulation, together
+    // with the tasks it spawns, returning its output. Fails with the seed if
+    // the future panics, or if no task can progress before it completes. Tasks
+    // still running then are dropped.
+    pub fn block_on<F>(&self, f: F) -> Result<F::Output, SimError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let world = Rc::new(World {
+            seed: self.config.seed,
+            rng: RefCell::new(FastRand::new(self.config.seed)),
+            config: self.config.clone(),
+            epoch: Instant::now(),
+            clock: Cell::new(Duration::ZERO),
+            timers: RefCell::new(BTreeMap::new()),
+            next_timer: Cell::new(0),
+            tasks: RefCell::new(BTreeMap::new()),
+            next_id: Cell::new(0),
+            ready: Arc::new(Mutex::new(BTreeSet::new())),
+            network: RefCell::new(net::Network::default()),
+        });
+
+        let previous = CURRENT.with(|current| current.replace(Some(world.clone())));
+        let result = world.run(f);
+
+        // Drop what is left while the simulation is still current, it may use it
+        let tasks = std::mem::take(&mut *world.tasks.borrow_mut());
+        drop(tasks);
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+}
+
+impl World {
+    fn run<F>(&self, f: F) -> Result<F::Output, SimError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let mut root = self.spawn(f);
+        let mut steps = 0;
+
+        loop {
+            if root.is_finished() {
+                let mut cx = Context::from_waker(Waker::noop());
+                let Poll::Ready(output) = Pin::new(&mut root).poll(&mut cx) else {
+                    unreachable!("a finished JoinHandle is ready");
+                };
+                return output.map_err(|err| match err {
+                    JoinError::Panicked(message) => SimError::Panicked {
+                        seed: self.seed,
+                        message,
+                    },
+                    JoinError::Cancelled => unreachable!("the root task cannot be aborted"),
+                });
+            }
+
+            steps += 1;
+            if steps > self.config.step_limit {
+                return Err(SimError::StepLimit {
+                    seed: self.seed,
+                    steps: self.config.step_limit,
+                });
+            }
+
+            if !self.poll_next() && !self.fire_next_timer() {
+                return Err(SimError::Deadlock {
+                    seed: self.seed,
+                    time: self.clock.get(),
+                });
+            }
+        }
+    }
+
+    // Function to poll a ready task, picked at random. Returns false if none is ready.
+    fn poll_next(&self) -> bool {
+        let id = {
+            let mut ready = self.ready.lock().unwrap();
+            if ready.is_empty() {
+                return false;
+            }
+            let pick = self.rng.borrow_mut().below(ready.len());
+            let id = *ready.iter().nth(pick).unwrap();
+            ready.remove(&id);
+            id
+        };
+
+        // Take the task out while it runs, it may spawn others
+        let Some(mut future) = self.tasks.borrow_mut().remove(&id) else {
+            return true; // Woken after completion
+        };
+
+        let waker = self.waker(id);
+        let mut cx = Context::from_waker(&waker);
+        if future.as_mut().poll(&mut cx).is_pending() {
+            self.tasks.borrow_mut().insert(id, future);
+        }
+        true
+    }
+
+    // Function to move the clock to the earliest timer and wake it. Returns
+    // false if no timer is left.
+    fn fire_next_timer(&self) -> bool {
+        let Some(((deadline, _), waker)) = self.timers.borrow_mut().pop_first() else {
+            return false;
+        };
+
+        self.clock.set(self.clock.get().max(deadline));
+        waker.wake();
+        true
+    }
+
+    fn spawn<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        let (future, handle) = join_handle::local(f, self.waker(id));
+        self.tasks.borrow_mut().insert(id, future);
+        self.ready.lock().unwrap().insert(id);
+        handle
+    }
+
+    fn waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }))
+    }
+
+    // Function to wake the waker once the clock reaches the deadline,
+    // returning the key to remove the timer with
+    fn add_timer(&self, deadline: Duration, waker: Waker) -> TimerKey {
+        let key = (deadline, self.next_timer.get());
+        self.next_timer.set(key.1 + 1);
+        self.timers.borrow_mut().insert(key, waker);
+        key
+    }
+
+    fn remove_timer(&self, key: TimerKey) {
+        self.timers.borrow_mut().remove(&key);
+    }
+
+    // Draw the delay of a segment sent on the network
+    fn latency(&self) -> Duration {
+        let (min, max) = (self.config.min_latency, self.config.max_latency);
+        let spread = (max - min).as_nanos() as usize;
+        min + Duration::from_nanos(self.rng.borrow_mut().below(spread + 1) as u64)
+    }
+
+    // Draw whether a write resets its connection
+    fn reset(&self) -> bool {
+        let draw = self.rng.borrow_mut().next_u64() as f64 / u64::MAX as f64;
+        draw < self.config.reset_rate
+    }
+}
+
+// Function to access the simulation running on the current thread. Panics outside of one.
+fn with_world<R>(f: impl FnOnce(&World) -> R) -> R {
+    try_with_world(f).expect("simulated time and network used outside of a simulation")
+}
+
+fn try_with_world<R>(f: impl FnOnce(&World) -> R) -> Option<R> {
+    let world = CURRENT.with(|current| current.borrow().clone());
+    world.map(|world| f(&world))
+}
+
+// Function to spawn a future onto the simulation running on the current
+// thread, returning a handle to await its output. Panics outside of one.
+pub fn spawn<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    with_world(|world| world.spawn(f))
+}
+
+// Get the seed of the simulation running on the current thread, if any
+pub fn seed() -> Option<u64> {
+    try_with_world(|world| world.seed)
+}
+
+// Check whether a simulation runs on the current thread
+pub fn is_current() -> bool {
+    CURRENT.with(|current| current.borrow().is_some())
+}
+
+// Key of a timer registered with a simulation: deadline, then registration order
+pub type TimerKey = (Duration, u64);
+
+// Get the virtual time of the simulation running on the current thread as an
+// Instant, for runtime::time. None outside of a simulation.
+pub fn instant() -> Option<Instant> {
+    try_with_world(|world| world.epoch + world.clock.get())
+}
+
+// Function to wake the waker once the virtual clock reaches the deadline.
+// Panics outside of a simulation.
+pub fn add_timer(deadline: Instant, waker: Waker) -> TimerKey {
+    with_world(|world| world.add_timer(deadline.saturating_duration_since(world.epoch), waker))
+}
+
+// Function to cancel a timer. Does nothing once the simulation is gone.
+pub fn remove_timer(key: TimerKey) {
+    try_with_world(|world| world.remove_timer(key));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::Builder;
+    use crate::net::SocketAddrV4;
+    use crate::runtime::executor;
+    use crate::runtime::net::tcp_listener::TcpListener;
+    use crate::runtime::net::tcp_stream::TcpStream;
+    use crate::runtime::time;
+
+    // Echo server written against the runtime, with clients racing it, recording
+    // every step of the run with the virtual time it happened at
+    async fn echo(trace: Arc<Mutex<Vec<String>>>) {
+        let addr = SocketAddrV4::new([127, 0, 0, 1], 8080);
+        let listener = TcpListener::bind(addr).unwrap();
+
+        let record = move |event: String| {
+            trace
+                .lock()
+                .unwrap()
+                .push(format!("{:?} {event}", super::now()));
+        };
+
+        let server = executor::spawn({
+            let record = record.clone();
+            async move {
+                for _ in 0..3 {
+                    let (mut stream, peer) = listener.accept().await.unwrap();
+                    record(format!("accepted {}", peer.port()));
+
+                    let record = record.clone();
+                    executor::spawn(async move {
+                        let mut buff = [0; 16];
+                        while let Ok(n) = stream.read(&mut buff).await {
+                            time::sleep(Duration::from_millis(1)).await;
+                            stream.write(&buff[..n as usize]).await.unwrap();
+                        }
+                        record("handler done".to_string());
+                    });
+                }
+            }
+        });
+
+        let clients: Vec<_> = (0..3u8)
+            .map(|client| {
+                let record = record.clone();
+                executor::spawn(async move {
+                    let stream = super::TcpStream::connect(addr).await.unwrap();
+                    let mut stream = TcpStream::from(stream);
+                    for round in 0..2u8 {
+                        stream.write(&[client, round]).await.unwrap();
+                        let mut buff = [0; 2];
+                        stream.read(&mut buff).await.unwrap();
+                        record(format!("client {client} got {buff:?}"));
+                    }
+                })
+            })
+            .collect();
+
+        server.await.unwrap();
+        for client in clients {
+            client.await.unwrap();
+        }
+    }
+
+    fn trace(seed: u64) -> Vec<String> {
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        Builder::new()
+            .seed(seed)
+            .build()
+            .block_on(echo(trace.clone()))
+            .unwrap();
+        let trace = trace.lock().unwrap().clone();
+        trace
+    }
+
+    #[test]
+    fn same_seed_replays_same_interleaving() {
+        for seed in 0..20 {
+            assert_eq!(trace(seed), trace(seed), "seed {seed} did not replay");
+        }
+    }
+
+    #[test]
+    fn seed_drives_interleaving() {
+        let first = trace(0);
+        assert!((1..20).any(|seed| trace(seed) != first));
+    }
+
+    #[test]
+    fn sleep_follows_virtual_clock() {
+        let elapsed = Builder::new()
+            .seed(7)
+            .build()
+            .block_on(async {
+                let start = time::now();
+                time::sleep(Duration::from_secs(3600)).await;
+                time::now() - start
+            })
+            .unwrap();
+        assert_eq!(elapsed, Duration::from_secs(3600));
+    }
+}
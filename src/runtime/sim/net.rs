@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use super::{try_with_world, with_world, TimerKey, World};
+use crate::core::error::IOError;
+use crate::core::result::Result;
+use crate::net::SocketAddrV4;
+use crate::runtime::ready::Ready;
+use crate::runtime::time::{self, Sleep};
+
+// First port handed out to the connecting side of a connection, or to a listener bound to port 0
+const EPHEMERAL_PORT: u16 = 49152;
+
+// State of the in-memory network of a simulation. It stands for a single
+// host: listeners are found by port, whatever the address they are bound to.
+pub struct Network {
+    listeners: BTreeMap<u16, Arc<Mutex<Backlog>>>,
+    next_port: u16, // Next ephemeral port to hand out
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network {
+            listeners: BTreeMap::new(),
+            next_port: EPHEMERAL_PORT,
+        }
+    }
+}
+
+impl Network {
+    fn ephemeral_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORT);
+        port
+    }
+}
+
+// Connections reached a listener and not accepted yet
+struct Backlog {
+    pending: VecDeque<(TcpStream, SocketAddrV4)>,
+    waker: Option<Waker>, // Task waiting in accept
+}
+
+// One direction of a simulated connection
+#[derive(Default)]
+struct Pipe {
+    segments: VecDeque<(Duration, Vec<u8>)>, // Data written and not read yet, by arrival time
+    last_at: Duration, // Arrival time of the last segment, later ones keep the order
+    fin_at: Option<Duration>, // Arrival time of the end of stream, once the writer is dropped
+    reset: bool,       // Set once the connection was reset
+    reader_gone: bool, // Set once the reading side is dropped
+    waker: Option<Waker>, // Task waiting to read
+    timer: Option<TimerKey>, // Wakes the reader once the next segment or the end of stream arrives
+}
+
+impl Pipe {
+    // Function to reset the pipe, waking its reader
+    fn reset(&mut self) {
+        self.reset = true;
+        self.segments.clear();
+        self.wake();
+    }
+
+    // Function to wake the reader, so it looks at the pipe again
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        if let Some(key) = self.timer.take() {
+            super::remove_timer(key);
+        }
+    }
+
+    // Arrival time of what the reader gets next: a segment, or the end of stream
+    fn next_at(&self) -> Option<Duration> {
+        match self.segments.front() {
+            Some((at, _)) => Some(*at),
+            None => self.fin_at,
+        }
+    }
+
+    // Function to make the reader wait for what it gets next, woken by the
+    // timer once it arrives, or by the peer when it writes, closes or resets
+    fn wait(&mut self, world: &World, cx: &mut Context<'_>) {
+        self.waker = Some(cx.waker().clone());
+        if let Some(key) = self.timer.take() {
+            world.remove_timer(key);
+        }
+        if let Some(at) = self.next_at() {
+            self.timer = Some(world.add_timer(at, cx.waker().clone()));
+        }
+    }
+}
+
+// Struct representing a TCP stream on the simulated network. Segments reach the
+// peer after a delay drawn from the seed, in the order they were written.
+// Inside a simulation, runtime::net::TcpStream wraps one.
+pub struct TcpStream {
+    inbound: Arc<Mutex<Pipe>>,  // Data sent by the peer
+    outbound: Arc<Mutex<Pipe>>, // Data sent to the peer
+    local: SocketAddrV4,
+    peer: SocketAddrV4,
+}
+
+impl TcpStream {
+    // Function to open a connection to the listener bound to the port of the
+    // address. Fails with ConnectionRefused if there is none once the SYN arrives.
+    pub fn connect(addr: SocketAddrV4) -> Connect {
+        Connect {
+            addr,
+            delay: time::sleep(with_world(|world| world.latency())),
+        }
+    }
+
+    // Function to read the data that reached the stream into the buffer, waiting
+    // for some if none did yet
+    pub fn read<'a>(&'a self, buff: &'a mut [u8]) -> Read<'a> {
+        Read { stream: self, buff }
+    }
+
+    // Function to send the buffer to the peer. Writes never wait: the simulated
+    // network buffers everything, but a write may reset the connection instead.
+    pub fn write<'a>(&'a self, buff: &'a [u8]) -> Write<'a> {
+        Write { stream: self, buff }
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.local
+    }
+
+    pub fn peer_addr(&self) -> SocketAddrV4 {
+        self.peer
+    }
+
+    // Function to read the data that reached the stream into the buffer,
+    // registering the task waker if none did yet
+    pub fn poll_read(&self, cx: &mut Context<'_>, buff: &mut [u8]) -> Poll<Result<isize>> {
+        with_world(|world| {
+            let now = world.clock.get();
+            let mut pipe = self.inbound.lock().unwrap();
+
+            if pipe.reset {
+                return Poll::Ready(Err(IOError::ConnectionReset));
+            }
+
+            // Copy from the segments that arrived, leaving the rest of a partly read one
+            let mut read = 0;
+            while read < buff.len() {
+                let Some((at, segment)) = pipe.segments.front_mut() else {
+                    break;
+                };
+                if *at > now {
+                    break;
+                }
+
+                let n = segment.len().min(buff.len() - read);
+                buff[read..read + n].copy_from_slice(&segment[..n]);
+                segment.drain(..n);
+                read += n;
+                if segment.is_empty() {
+                    pipe.segments.pop_front();
+                }
+            }
+
+            if read > 0 || buff.is_empty() {
+                return Poll::Ready(Ok(read as isize));
+            }
+
+            match pipe.next_at() {
+                Some(at) if at <= now => Poll::Ready(Err(IOError::ConnectionClosed)),
+                _ => {
+                    pipe.wait(world, cx);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    // Function to send the buffer to the peer. Never pending.
+    pub fn poll_write(&self, buff: &[u8]) -> Poll<Result<isize>> {
+        {
+            let pipe = self.outbound.lock().unwrap();
+            if pipe.reset {
+                return Poll::Ready(Err(IOError::ConnectionReset));
+            }
+            if pipe.reader_gone {
+                // Like a real peer answering data sent after it closed with a RST
+                drop(pipe);
+                self.reset();
+                return Poll::Ready(Err(IOError::ConnectionReset));
+            }
+        }
+
+        let (now, latency, reset) =
+            with_world(|world| (world.clock.get(), world.latency(), world.reset()));
+        if reset {
+            self.reset();
+            return Poll::Ready(Err(IOError::ConnectionReset));
+        }
+
+        let mut pipe = self.outbound.lock().unwrap();
+        let at = (now + latency).max(pipe.last_at);
+        pipe.last_at = at;
+        pipe.segments.push_back((at, buff.to_vec()));
+        pipe.wake();
+
+        Poll::Ready(Ok(buff.len() as isize))
+    }
+
+    // Function to check whether the stream is ready for reading, registering
+    // the task waker otherwise
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<Ready>> {
+        with_world(|world| {
+            let now = world.clock.get();
+            let mut pipe = self.inbound.lock().unwrap();
+
+            if pipe.reset {
+                return Poll::Ready(Ok(Ready::ERROR | Ready::READ_CLOSED));
+            }
+            match (pipe.segments.front(), pipe.fin_at) {
+                (Some((at, _)), _) if *at <= now => Poll::Ready(Ok(Ready::READABLE)),
+                (None, Some(at)) if at <= now => Poll::Ready(Ok(Ready::READ_CLOSED)),
+                _ => {
+                    pipe.wait(world, cx);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    // Function to check whether the stream is ready for writing. Always ready:
+    // the simulated network buffers everything.
+    pub fn poll_write_ready(&self, _cx: &mut Context<'_>) -> Poll<Result<Ready>> {
+        if self.outbound.lock().unwrap().reset {
+            return Poll::Ready(Ok(Ready::ERROR | Ready::WRITE_CLOSED));
+        }
+        Poll::Ready(Ok(Ready::WRITABLE))
+    }
+
+    // Function to reset both directions of the connection, waking both ends
+    fn reset(&self) {
+        self.inbound.lock().unwrap().reset();
+        self.outbound.lock().unwrap().reset();
+    }
+}
+
+// Drop implementation to close the stream: the peer reads the end of stream
+// after the data already sent, and its writes fail from then on
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let latency = try_with_world(|world| (world.clock.get(), world.latency()));
+
+        let mut outbound = self.outbound.lock().unwrap();
+        let fin_at = match latency {
+            Some((now, latency)) => (now + latency).max(outbound.last_at),
+            None => outbound.last_at,
+        };
+        outbound.fin_at = Some(fin_at);
+        outbound.wake();
+        drop(outbound);
+
+        let mut inbound = self.inbound.lock().unwrap();
+        inbound.reader_gone = true;
+        inbound.segments.clear();
+        if let Some(key) = inbound.timer.take() {
+            super::remove_timer(key);
+        }
+    }
+}
+
+// Future for opening a connection on the simulated network
+pub struct Connect {
+    addr: SocketAddrV4,
+    delay: Sleep, // Time for the SYN to reach the listener
+}
+
+impl Future for Connect {
+    type Output = Result<TcpStream>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        if Pin::new(&mut state.delay).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let (backlog, port) = with_world(|world| {
+            let mut network = world.network.borrow_mut();
+            let backlog = network.listeners.get(&state.addr.port()).cloned();
+            (backlog, network.ephemeral_port())
+        });
+        let Some(backlog) = backlog else {
+            return Poll::Ready(Err(IOError::ConnectionRefused));
+        };
+
+        let local = SocketAddrV4::new([127, 0, 0, 1], port);
+        let to_server = Arc::new(Mutex::new(Pipe::default()));
+        let to_client = Arc::new(Mutex::new(Pipe::default()));
+
+        let server = TcpStream {
+            inbound: to_server.clone(),
+            outbound: to_client.clone(),
+            local: state.addr,
+            peer: local,
+        };
+
+        let mut backlog = backlog.lock().unwrap();
+        backlog.pending.push_back((server, local));
+        if let Some(waker) = backlog.waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(TcpStream {
+            inbound: to_client,
+            outbound: to_server,
+            local,
+            peer: state.addr,
+        }))
+    }
+}
+
+// Future for reading from a simulated stream
+pub struct Read<'a> {
+    stream: &'a TcpStream,
+    buff: &'a mut [u8],
+}
+
+impl Future for Read<'_> {
+    type Output = Result<isize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        state.stream.poll_read(cx, state.buff)
+    }
+}
+
+// Future for writing to a simulated stream
+pub struct Write<'a> {
+    stream: &'a TcpStream,
+    buff: &'a [u8],
+}
+
+impl Future for Write<'_> {
+    type Output = Result<isize>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_write(self.buff)
+    }
+}
+
+// Struct representing a TCP listener on the simulated network. Inside a
+// simulation, runtime::net::TcpListener wraps one.
+pub struct TcpListener {
+    backlog: Arc<Mutex<Backlog>>,
+    addr: SocketAddrV4,
+}
+
+impl TcpListener {
+    // Constructor to create a TcpListener bound to the port of the address, or
+    // to an ephemeral one for port 0
+    pub fn bind(addr: SocketAddrV4) -> Result<TcpListener> {
+        with_world(|world| {
+            let mut network = world.network.borrow_mut();
+            let port = match addr.port() {
+                0 => network.ephemeral_port(),
+                port => port,
+            };
+            if network.listeners.contains_key(&port) {
+                return Err(IOError::SyscallResult("Address already in use".to_string()));
+            }
+
+            let backlog = Arc::new(Mutex::new(Backlog {
+                pending: VecDeque::new(),
+                waker: None,
+            }));
+            network.listeners.insert(port, backlog.clone());
+
+            Ok(TcpListener {
+                backlog,
+                addr: SocketAddrV4::new(addr.ip_octets(), port),
+            })
+        })
+    }
+
+    // Function to accept the next connection, waiting for one if none is pending
+    pub fn accept(&self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+
+    // Function to take the next pending connection, registering the task waker
+    // if there is none
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(TcpStream, SocketAddrV4)>> {
+        let mut backlog = self.backlog.lock().unwrap();
+        match backlog.pending.pop_front() {
+            Some(connection) => Poll::Ready(Ok(connection)),
+            None => {
+                backlog.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.addr
+    }
+}
+
+// Drop implementation to free the port. Connections not accepted are closed.
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        try_with_world(|world| {
+            world
+                .network
+                .borrow_mut()
+                .listeners
+                .remove(&self.addr.port());
+        });
+        let pending = std::mem::take(&mut self.backlog.lock().unwrap().pending);
+        drop(pending);
+    }
+}
+
+// Future for accepting a connection on the simulated network
+pub struct Accept<'listener> {
+    listener: &'listener TcpListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = Result<(TcpStream, SocketAddrV4)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.listener.poll_accept(cx)
+    }
+}
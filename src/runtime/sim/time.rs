@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use super::{instant, with_world};
+use crate::runtime::time::{self, Sleep};
+
+pub use crate::runtime::time::timeout;
+
+// Get the virtual time elapsed since the simulation started. Panics outside of one.
+pub fn now() -> Duration {
+    with_world(|world| world.clock.get())
+}
+
+// Function to create a future completing once the given virtual duration has
+// elapsed, the same as runtime::time::sleep inside a simulation
+pub fn sleep(duration: Duration) -> Sleep {
+    time::sleep(duration)
+}
+
+// Function to create a future completing once the virtual clock reaches the
+// deadline, counted from the start of the simulation. Panics outside of one.
+pub fn sleep_until(deadline: Duration) -> Sleep {
+    let start = instant().expect("simulated time used outside of a simulation") - now();
+    time::sleep_until(start + deadline)
+}
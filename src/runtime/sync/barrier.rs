@@ -0,0 +1,184 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use super::WaitList;
+
+// Struct representing a barrier: tasks calling wait are parked until `parties`
+// of them arrived, then they are all released and the barrier can be reused
+pub struct Barrier {
+    state: Mutex<State>,
+    parties: usize,
+}
+
+struct State {
+    arrived: usize,    // Tasks waiting in the current generation
+    generation: u64,   // Incremented every time the barrier releases its tasks
+    waiters: WaitList, // Tasks waiting in the current generation
+}
+
+// Outcome of a wait on a barrier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    // Check whether the task was the last one to arrive. Exactly one task of
+    // every generation is the leader.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    pub fn new(parties: usize) -> Self {
+        Barrier {
+            state: Mutex::new(State {
+                arrived: 0,
+                generation: 0,
+                waiters: WaitList::new(),
+            }),
+            parties,
+        }
+    }
+
+    // Function to wait until every party arrived
+    pub fn wait(&self) -> Wait<'_> {
+        Wait {
+            barrier: self,
+            generation: None,
+            key: None,
+        }
+    }
+}
+
+// Future waiting at a barrier
+pub struct Wait<'a> {
+    barrier: &'a Barrier,
+    generation: Option<u64>, // Generation joined, once arrived
+    key: Option<u64>,        // Key in the waiters, once waiting
+}
+
+impl Future for Wait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let wait = self.get_mut();
+        let mut state = wait.barrier.state.lock().unwrap();
+
+        match wait.generation {
+            None => {
+                state.arrived += 1;
+                if state.arrived >= wait.barrier.parties {
+                    // Last one in, release the others and start a new generation
+                    state.arrived = 0;
+                    state.generation += 1;
+                    state.waiters.wake_all();
+                    return Poll::Ready(BarrierWaitResult(true));
+                }
+                wait.generation = Some(state.generation);
+            }
+            Some(generation) if generation != state.generation => {
+                wait.generation = None;
+                wait.key = None;
+                return Poll::Ready(BarrierWaitResult(false));
+            }
+            Some(_) => {}
+        }
+
+        state.waiters.register(&mut wait.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait<'_> {
+    // A task giving up before the barrier released it no longer counts as arrived
+    fn drop(&mut self) {
+        if let Some(generation) = self.generation {
+            let mut state = self.barrier.state.lock().unwrap();
+            if generation == state.generation {
+                state.arrived -= 1;
+                if let Some(key) = self.key {
+                    state.waiters.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::{Barrier, BarrierWaitResult};
+
+    #[test]
+    fn releases_every_generation() {
+        let barrier = Barrier::new(3);
+        let (flag, waker) = Flag::waker();
+
+        for _ in 0..3 {
+            let mut first = barrier.wait();
+            let mut second = barrier.wait();
+            assert!(poll(&mut first, &waker).is_pending());
+            assert!(poll(&mut second, &waker).is_pending());
+            assert!(!flag.take());
+
+            // The last one in leads and releases the others
+            assert_eq!(
+                poll(&mut barrier.wait(), &waker),
+                Poll::Ready(BarrierWaitResult(true))
+            );
+            assert!(flag.take());
+            assert_eq!(
+                poll(&mut first, &waker),
+                Poll::Ready(BarrierWaitResult(false))
+            );
+            assert_eq!(
+                poll(&mut second, &waker),
+                Poll::Ready(BarrierWaitResult(false))
+            );
+        }
+    }
+
+    #[test]
+    fn released_waiters_do_not_count_in_the_next_generation() {
+        let barrier = Barrier::new(2);
+        let (_, waker) = Flag::waker();
+
+        // Released, but only polled again once the next generation started
+        let mut late = barrier.wait();
+        assert!(poll(&mut late, &waker).is_pending());
+        assert!(poll(&mut barrier.wait(), &waker).is_ready());
+
+        // The late waiter completes without releasing the new one
+        let mut next = barrier.wait();
+        assert!(poll(&mut next, &waker).is_pending());
+        assert_eq!(
+            poll(&mut late, &waker),
+            Poll::Ready(BarrierWaitResult(false))
+        );
+        assert!(poll(&mut next, &waker).is_pending());
+        assert_eq!(
+            poll(&mut barrier.wait(), &waker),
+            Poll::Ready(BarrierWaitResult(true))
+        );
+        assert!(poll(&mut next, &waker).is_ready());
+    }
+
+    #[test]
+    fn dropped_waiter_no_longer_counts() {
+        let barrier = Barrier::new(2);
+        let (_, waker) = Flag::waker();
+
+        let mut gone = barrier.wait();
+        assert!(poll(&mut gone, &waker).is_pending());
+        drop(gone);
+
+        let mut first = barrier.wait();
+        assert!(poll(&mut first, &waker).is_pending());
+        assert!(poll(&mut barrier.wait(), &waker).is_ready());
+        assert!(poll(&mut first, &waker).is_ready());
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use super::WaitList;
+use crate::core::error::{RecvError, SendError, TryRecvError};
+
+// Function to create a channel delivering every value to every receiver. The
+// channel keeps the last `capacity` values, a receiver falling further behind
+// misses the oldest ones.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "a broadcast channel needs a capacity of at least one"
+    );
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            head: 0,
+            senders: 1,
+            receivers: 1,
+            waiters: WaitList::new(),
+        }),
+        capacity,
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, next: 0 },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+}
+
+struct State<T> {
+    buffer: VecDeque<T>, // Last values sent, oldest first
+    head: u64,           // Position of the oldest value in the buffer
+    senders: usize,
+    receivers: usize,
+    waiters: WaitList, // Receivers waiting for the next value
+}
+
+impl<T> State<T> {
+    // Position the next value sent will take
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+}
+
+// Struct representing the sending side of a broadcast channel
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    // Function to send a value to every receiver, returns how many there are.
+    // Fails with the value if there is none.
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+
+        if state.buffer.len() == self.shared.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        state.waiters.wake_all();
+        Ok(state.receivers)
+    }
+
+    // Function to create a receiver getting the values sent from now on
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            next: state.tail(),
+        }
+    }
+
+    // Get the number of live receivers
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock().unwrap().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.waiters.wake_all();
+        }
+    }
+}
+
+// Struct representing a receiving side of a broadcast channel
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    next: u64, // Position of the next value to receive
+}
+
+impl<T: Clone> Receiver<T> {
+    // Function to receive the next value. Fails with RecvError::Lagged if values
+    // were missed, the following recv returns the oldest value still kept.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv {
+            receiver: self,
+            key: None,
+        }
+    }
+
+    // Function to receive a value only if one is available right now. Values
+    // missed by falling behind are skipped.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let mut next = next_value(&mut self.next, &mut state);
+        if let Some(Err(RecvError::Lagged(_))) = next {
+            next = next_value(&mut self.next, &mut state);
+        }
+
+        match next {
+            Some(Ok(value)) => Ok(value),
+            _ if state.senders == 0 => Err(TryRecvError::Closed),
+            _ => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+// Take the value at position `next` if it was sent, catching up if it is no longer kept
+fn next_value<T: Clone>(next: &mut u64, state: &mut State<T>) -> Option<Result<T, RecvError>> {
+    if *next < state.head {
+        let missed = state.head - *next;
+        *next = state.head;
+        return Some(Err(RecvError::Lagged(missed)));
+    }
+
+    let value = state.buffer.get((*next - state.head) as usize)?.clone();
+    *next += 1;
+    Some(Ok(value))
+}
+
+impl<T> Clone for Receiver<T> {
+    // The clone receives the same values as the original from now on
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receivers -= 1;
+    }
+}
+
+// Future receiving the next value of a broadcast channel
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+    key: Option<u64>, // Key in the waiters, once waiting
+}
+
+impl<T: Clone> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let recv = self.get_mut();
+        let receiver = &mut *recv.receiver;
+        let mut state = receiver.shared.state.lock().unwrap();
+
+        if let Some(result) = next_value(&mut receiver.next, &mut state) {
+            return Poll::Ready(result);
+        }
+        if state.senders == 0 {
+            return Poll::Ready(Err(RecvError::Closed));
+        }
+
+        state.waiters.register(&mut recv.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Recv<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.receiver
+                .shared
+                .state
+                .lock()
+                .unwrap()
+                .waiters
+                .remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::channel;
+    use crate::core::error::{RecvError, TryRecvError};
+
+    #[test]
+    fn every_receiver_gets_every_value() {
+        let (tx, mut first) = channel(4);
+        let mut second = tx.subscribe();
+        let (flag, waker) = Flag::waker();
+
+        let mut recv = first.recv();
+        assert!(poll(&mut recv, &waker).is_pending());
+        assert_eq!(tx.send(1).unwrap(), 2);
+        assert!(flag.take());
+        assert_eq!(poll(&mut recv, &waker), Poll::Ready(Ok(1)));
+        drop(recv);
+
+        tx.send(2).unwrap();
+        assert_eq!(first.try_recv(), Ok(2));
+        assert_eq!(second.try_recv(), Ok(1));
+        assert_eq!(second.try_recv(), Ok(2));
+        assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+
+        // A late subscriber only sees the values sent from then on
+        let mut late = tx.subscribe();
+        assert_eq!(late.try_recv(), Err(TryRecvError::Empty));
+        tx.send(3).unwrap();
+        assert_eq!(late.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn slow_receiver_lags() {
+        let (tx, mut rx) = channel(2);
+        let (_, waker) = Flag::waker();
+
+        for value in 0..5 {
+            tx.send(value).unwrap();
+        }
+
+        // Values 0 to 2 were overwritten, the receiver resumes at the oldest kept
+        assert_eq!(
+            poll(&mut rx.recv(), &waker),
+            Poll::Ready(Err(RecvError::Lagged(3)))
+        );
+        assert_eq!(poll(&mut rx.recv(), &waker), Poll::Ready(Ok(3)));
+        assert_eq!(poll(&mut rx.recv(), &waker), Poll::Ready(Ok(4)));
+
+        // try_recv skips the missed values
+        for value in 5..10 {
+            tx.send(value).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Ok(8));
+        assert_eq!(rx.try_recv(), Ok(9));
+    }
+
+    #[test]
+    fn recv_ends_once_every_sender_is_gone() {
+        let (tx, mut rx) = channel(2);
+        let (flag, waker) = Flag::waker();
+        let other = tx.clone();
+
+        tx.send(1).unwrap();
+        drop(tx);
+
+        // The values already sent are still received first
+        let mut recv = rx.recv();
+        assert_eq!(poll(&mut recv, &waker), Poll::Ready(Ok(1)));
+        drop(recv);
+
+        let mut recv = rx.recv();
+        assert!(poll(&mut recv, &waker).is_pending());
+        drop(other);
+        assert!(flag.take());
+        assert_eq!(poll(&mut recv, &waker), Poll::Ready(Err(RecvError::Closed)));
+        drop(recv);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (tx, rx) = channel(2);
+        let clone = rx.clone();
+        assert_eq!(tx.receiver_count(), 2);
+
+        drop(rx);
+        assert_eq!(tx.send(1).unwrap(), 1);
+        drop(clone);
+        assert_eq!(tx.receiver_count(), 0);
+        assert_eq!(tx.send(2).unwrap_err().0, 2);
+    }
+}
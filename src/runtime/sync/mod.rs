@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::task::Waker;
+
+pub mod barrier;
+pub mod broadcast;
+pub mod mpsc;
+pub mod mutex;
+pub mod notify;
+pub mod oneshot;
+pub mod rwlock;
+pub mod semaphore;
+pub mod watch;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::Notify;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+// Struct representing the tasks waiting on a synchronization primitive, in
+// arrival order. Every waiter has a key so a future dropped while waiting can
+// take itself out of the list.
+#[derive(Default)]
+pub struct WaitList {
+    waiters: VecDeque<(u64, Waker)>,
+    next_key: u64,
+}
+
+impl WaitList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Function to add a waiter, or refresh its waker if it is already waiting.
+    // The key is assigned on the first call and must be passed back afterwards.
+    pub fn register(&mut self, key: &mut Option<u64>, waker: &Waker) {
+        if let Some(key) = *key {
+            if let Some((_, current)) = self.waiters.iter_mut().find(|(k, _)| *k == key) {
+                current.clone_from(waker);
+                return;
+            }
+        }
+
+        let new_key = *key.get_or_insert_with(|| {
+            self.next_key += 1;
+            self.next_key
+        });
+        self.waiters.push_back((new_key, waker.clone()));
+    }
+
+    // Function to remove a waiter. Returns false if it was no longer waiting,
+    // i.e. it was woken and may have to pass the wakeup on.
+    pub fn remove(&mut self, key: u64) -> bool {
+        match self.waiters.iter().position(|(k, _)| *k == key) {
+            Some(index) => {
+                self.waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Check whether the waiter is still in the list
+    pub fn contains(&self, key: u64) -> bool {
+        self.waiters.iter().any(|(k, _)| *k == key)
+    }
+
+    // Get the key of the oldest waiter
+    pub fn front(&self) -> Option<u64> {
+        self.waiters.front().map(|(key, _)| *key)
+    }
+
+    // Function to wake the oldest waiter, returns false if there was none
+    pub fn wake_one(&mut self) -> bool {
+        match self.waiters.pop_front() {
+            Some((_, waker)) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Function to wake every waiter
+    pub fn wake_all(&mut self) {
+        for (_, waker) in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+}
+
+// Helpers polling futures by hand in the tests of the primitives
+#[cfg(test)]
+mod testing {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    // Waker remembering whether it was woken
+    #[derive(Default)]
+    pub struct Flag(AtomicBool);
+
+    impl Flag {
+        pub fn waker() -> (Arc<Flag>, Waker) {
+            let flag = Arc::new(Flag::default());
+            (flag.clone(), Waker::from(flag))
+        }
+
+        // Check whether the waker was woken, and reset it
+        pub fn take(&self) -> bool {
+            self.0.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // Poll a future once with the given waker
+    pub fn poll<F: Future + Unpin>(future: &mut F, waker: &Waker) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::WaitList;
+use crate::core::error::{SendError, TryRecvError, TrySendError};
+
+// Function to create a channel holding at most `capacity` values, senders wait
+// for room once it is full
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "a bounded channel needs a capacity of at least one"
+    );
+    let chan = Chan::new(Some(capacity));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+// Function to create a channel without limit, sending never waits
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let chan = Chan::new(None);
+    (
+        UnboundedSender { chan: chan.clone() },
+        UnboundedReceiver {
+            inner: Receiver { chan },
+        },
+    )
+}
+
+// State shared between the senders and the receiver
+struct Chan<T> {
+    state: Mutex<State<T>>,
+    capacity: Option<usize>, // None for an unbounded channel
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,          // Number of live senders
+    closed: bool,            // Set once the receiver is closed or dropped
+    receiver: Option<Waker>, // Task waiting in recv
+    send_waiters: WaitList,  // Tasks waiting for room in a full channel
+}
+
+impl<T> Chan<T> {
+    fn new(capacity: Option<usize>) -> Arc<Chan<T>> {
+        Arc::new(Chan {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                senders: 1,
+                closed: false,
+                receiver: None,
+                send_waiters: WaitList::new(),
+            }),
+            capacity,
+        })
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(TrySendError::Closed(value));
+        }
+        if self
+            .capacity
+            .is_some_and(|capacity| state.queue.len() >= capacity)
+        {
+            return Err(TrySendError::Full(value));
+        }
+
+        state.queue.push_back(value);
+        if let Some(waker) = state.receiver.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Self> {
+        self.state.lock().unwrap().senders += 1;
+        self.clone()
+    }
+
+    fn drop_sender(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // Let the receiver see the channel is closed
+            if let Some(waker) = state.receiver.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// Struct representing the sending side of a bounded channel
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    // Function to send a value, waiting for room if the channel is full. Fails
+    // with the value if the receiver is gone.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture {
+            chan: &self.chan,
+            value: Some(value),
+            key: None,
+        }
+    }
+
+    // Function to send a value only if there is room right now
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(value)
+    }
+
+    // Check whether the receiver is gone
+    pub fn is_closed(&self) -> bool {
+        self.chan.state.lock().unwrap().closed
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+// Future sending a value on a bounded channel
+pub struct SendFuture<'a, T> {
+    chan: &'a Arc<Chan<T>>,
+    value: Option<T>,
+    key: Option<u64>, // Key in the send waiters, once waiting
+}
+
+// The value is moved out by value, it is never pinned
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        let mut chan = state.chan.state.lock().unwrap();
+        let value = state
+            .value
+            .take()
+            .expect("SendFuture polled after completion");
+
+        if chan.closed {
+            return Poll::Ready(Err(SendError(value)));
+        }
+
+        // Queue behind the senders already waiting, so a full channel is fair
+        let waited = state
+            .key
+            .is_some_and(|key| !chan.send_waiters.contains(key));
+        let first = chan.send_waiters.is_empty() || waited;
+        let capacity = state.chan.capacity.unwrap_or(usize::MAX);
+
+        if first && chan.queue.len() < capacity {
+            chan.queue.push_back(value);
+            state.key = None;
+            if let Some(waker) = chan.receiver.take() {
+                waker.wake();
+            }
+            // There may be room for the next waiting sender as well
+            if chan.queue.len() < capacity {
+                chan.send_waiters.wake_one();
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        state.value = Some(value);
+        chan.send_waiters.register(&mut state.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    // Leave the waiters, passing the wakeup on if we were woken but never sent
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut chan = self.chan.state.lock().unwrap();
+            if !chan.send_waiters.remove(key) {
+                chan.send_waiters.wake_one();
+            }
+        }
+    }
+}
+
+// Struct representing the receiving side of a bounded channel
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    // Function to receive the next value, resolving to None once the channel is
+    // empty and every sender is gone
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    // Function to receive a value only if one is available right now
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.chan.state.lock().unwrap();
+        match self.pop(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // Function to close the channel: senders fail from now on, the values
+    // already queued can still be received
+    pub fn close(&mut self) {
+        let mut state = self.chan.state.lock().unwrap();
+        state.closed = true;
+        state.send_waiters.wake_all();
+    }
+
+    fn pop(&self, state: &mut State<T>) -> Option<T> {
+        let value = state.queue.pop_front()?;
+        state.send_waiters.wake_one();
+        Some(value)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// Future receiving the next value of a channel
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let receiver = &self.get_mut().receiver;
+        let mut state = receiver.chan.state.lock().unwrap();
+
+        if let Some(value) = receiver.pop(&mut state) {
+            return Poll::Ready(Some(value));
+        }
+        if state.senders == 0 || state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.receiver = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+// Struct representing the sending side of an unbounded channel
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    // Function to send a value, fails with the value if the receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan.try_send(value).map_err(|err| match err {
+            TrySendError::Closed(value) | TrySendError::Full(value) => SendError(value),
+        })
+    }
+
+    // Check whether the receiver is gone
+    pub fn is_closed(&self) -> bool {
+        self.chan.state.lock().unwrap().closed
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+// Struct representing the receiving side of an unbounded channel
+pub struct UnboundedReceiver<T> {
+    inner: Receiver<T>,
+}
+
+impl<T> UnboundedReceiver<T> {
+    // Function to receive the next value, resolving to None once the channel is
+    // empty and every sender is gone
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        self.inner.recv()
+    }
+
+    // Function to receive a value only if one is available right now
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    // Function to close the channel, the values already queued can still be received
+    pub fn close(&mut self) {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::{channel, unbounded_channel};
+    use crate::core::error::{TryRecvError, TrySendError};
+
+    #[test]
+    fn send_waits_for_room() {
+        let (tx, mut rx) = channel(2);
+        let (flag, waker) = Flag::waker();
+
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+
+        let mut send = tx.send(3);
+        assert!(poll(&mut send, &waker).is_pending());
+
+        // Receiving makes room and wakes the waiting sender
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(flag.take());
+        assert!(matches!(poll(&mut send, &waker), Poll::Ready(Ok(()))));
+        assert!(matches!(tx.try_send(4), Err(TrySendError::Full(4))));
+
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn waiting_senders_are_served_in_order() {
+        let (tx, mut rx) = channel(1);
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        tx.try_send(0).unwrap();
+        let mut first = tx.send(1);
+        let mut second = tx.send(2);
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        // The room goes to the oldest sender
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert!(first_flag.take());
+        assert!(!second_flag.take());
+        assert!(poll(&mut second, &second_waker).is_pending());
+        assert!(matches!(
+            poll(&mut first, &first_waker),
+            Poll::Ready(Ok(()))
+        ));
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(second_flag.take());
+        assert!(matches!(
+            poll(&mut second, &second_waker),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn cancelled_send_gives_back_its_slot() {
+        let (tx, mut rx) = channel(1);
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        tx.try_send(0).unwrap();
+        let mut first = tx.send(1);
+        let mut second = tx.send(2);
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        // Woken for the room, the first sender is dropped before sending: the
+        // wakeup passes on to the next one
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert!(first_flag.take());
+        drop(first);
+        assert!(second_flag.take());
+        assert!(matches!(
+            poll(&mut second, &second_waker),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(rx.try_recv(), Ok(2));
+
+        // Dropped while still queued, a sender no longer holds anyone back
+        tx.try_send(3).unwrap();
+        let mut waiting = tx.send(4);
+        assert!(poll(&mut waiting, &first_waker).is_pending());
+        drop(waiting);
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert!(!first_flag.take());
+        tx.try_send(5).unwrap();
+        assert_eq!(rx.try_recv(), Ok(5));
+    }
+
+    #[test]
+    fn recv_ends_once_every_sender_is_gone() {
+        let (tx, mut rx) = channel(4);
+        let (flag, waker) = Flag::waker();
+        let other = tx.clone();
+
+        tx.try_send(1).unwrap();
+        drop(tx);
+        assert!(matches!(poll(&mut rx.recv(), &waker), Poll::Ready(Some(1))));
+
+        let mut recv = rx.recv();
+        assert!(poll(&mut recv, &waker).is_pending());
+        drop(other);
+        assert!(flag.take());
+        assert!(matches!(poll(&mut recv, &waker), Poll::Ready(None)));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn send_fails_once_the_receiver_is_gone() {
+        let (tx, mut rx) = channel(1);
+        let (flag, waker) = Flag::waker();
+
+        tx.try_send(1).unwrap();
+        let mut send = tx.send(2);
+        assert!(poll(&mut send, &waker).is_pending());
+
+        // Closing fails the waiting senders, the queued values stay available
+        rx.close();
+        assert!(flag.take());
+        assert!(tx.is_closed());
+        assert!(matches!(poll(&mut send, &waker), Poll::Ready(Err(err)) if err.0 == 2));
+        assert!(matches!(tx.try_send(3), Err(TrySendError::Closed(3))));
+        assert_eq!(rx.try_recv(), Ok(1));
+
+        let (tx, rx) = unbounded_channel();
+        tx.send(1).unwrap();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(2).unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn unbounded_send_never_waits() {
+        let (tx, mut rx) = unbounded_channel();
+        for value in 0..1000 {
+            tx.send(value).unwrap();
+        }
+        drop(tx);
+
+        for value in 0..1000 {
+            assert_eq!(rx.try_recv(), Ok(value));
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+}
@@ -0,0 +1,176 @@
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::semaphore::{Acquire, Semaphore};
+
+// Struct representing a mutex held across awaits. Tasks waiting for the lock
+// are parked and get it in the order they asked for it.
+pub struct Mutex<T: ?Sized> {
+    semaphore: Semaphore, // One permit, held by the owner of the lock
+    value: UnsafeCell<T>,
+}
+
+// The semaphore gives the value to a single task at a time
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Mutex {
+            semaphore: Semaphore::new(1),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    // Function to wait for the lock, released when the guard is dropped
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            acquire: self.semaphore.acquire(),
+        }
+    }
+
+    // Function to take the lock only if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire()?;
+        permit.forget();
+        Some(MutexGuard { mutex: self })
+    }
+
+    // Get the value through a unique reference, no locking needed
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+// Future waiting for the lock of a Mutex
+pub struct Lock<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    acquire: Acquire<'a>,
+}
+
+impl<'a, T: ?Sized> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.get_mut();
+        match Pin::new(&mut lock.acquire).poll(cx) {
+            Poll::Ready(permit) => {
+                // The guard gives the permit back itself
+                permit.forget();
+                Poll::Ready(MutexGuard { mutex: lock.mutex })
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// Guard giving access to the value of a locked Mutex
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+// Shared guards only hand out shared references
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.semaphore.add_permits(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::Mutex;
+
+    #[test]
+    fn lock_is_granted_in_request_order() {
+        let mutex = Mutex::new(Vec::new());
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        let guard = mutex.try_lock().unwrap();
+        let mut first = mutex.lock();
+        let mut second = mutex.lock();
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+        assert!(first_flag.take());
+        assert!(!second_flag.take());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        // Handed to the first waiter, the lock stays taken until it polls
+        assert!(mutex.try_lock().is_none());
+        let Poll::Ready(mut guard) = poll(&mut first, &first_waker) else {
+            panic!("the lock was handed over");
+        };
+        guard.push(1);
+        drop(guard);
+
+        assert!(second_flag.take());
+        let Poll::Ready(mut guard) = poll(&mut second, &second_waker) else {
+            panic!("the lock was handed over");
+        };
+        guard.push(2);
+        drop(guard);
+        drop((first, second));
+        assert_eq!(mutex.into_inner(), vec![1, 2]);
+    }
+
+    #[test]
+    fn dropped_lock_passes_the_lock_on() {
+        let mutex = Mutex::new(0);
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        let guard = mutex.try_lock().unwrap();
+        let mut first = mutex.lock();
+        let mut second = mutex.lock();
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        // Woken with the lock, the first waiter gives up before taking it
+        drop(guard);
+        assert!(first_flag.take());
+        drop(first);
+        assert!(second_flag.take());
+        assert!(poll(&mut second, &second_waker).is_ready());
+
+        // The guard of the second waiter was dropped right away
+        assert!(mutex.try_lock().is_some());
+    }
+}
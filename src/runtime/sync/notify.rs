@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use super::WaitList;
+
+// Struct representing a way for tasks to wait for an event another task
+// signals, without any data attached
+#[derive(Default)]
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    permit: bool,         // Stored by notify_one when nobody was waiting
+    waiters: WaitList,    // Tasks waiting in notified
+    handed: HashSet<u64>, // Waiters woken by notify_one, that must pass it on if dropped
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Function to wait for a notification
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            key: None,
+        }
+    }
+
+    // Function to wake the oldest waiting task. If none is waiting, the next
+    // call to notified completes right away.
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.notify_one();
+    }
+
+    // Function to wake every task waiting right now, without storing a
+    // notification for later ones
+    pub fn notify_waiters(&self) {
+        self.state.lock().unwrap().waiters.wake_all();
+    }
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        // The wakeup is for the waiter at the front, remember it was handed one
+        let front = self.waiters.front();
+        if self.waiters.wake_one() {
+            self.handed.extend(front);
+        } else {
+            self.permit = true;
+        }
+    }
+}
+
+// Future waiting for a notification
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    key: Option<u64>, // Key in the waiters, once waiting
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let notified = self.get_mut();
+        let mut state = notified.notify.state.lock().unwrap();
+
+        match notified.key {
+            None if state.permit => {
+                state.permit = false;
+                return Poll::Ready(());
+            }
+            // Only a notification takes a waiter out of the list
+            Some(key) if !state.waiters.contains(key) => {
+                state.handed.remove(&key);
+                notified.key = None;
+                return Poll::Ready(());
+            }
+            _ => {}
+        }
+
+        state.waiters.register(&mut notified.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    // Leave the waiters, passing a notify_one wakeup on if it was never used
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.notify.state.lock().unwrap();
+            if !state.waiters.remove(key) && state.handed.remove(&key) {
+                state.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{poll, Flag};
+    use super::Notify;
+
+    #[test]
+    fn notify_one_wakes_the_oldest_waiter() {
+        let notify = Notify::new();
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        let mut first = notify.notified();
+        let mut second = notify.notified();
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        notify.notify_one();
+        assert!(first_flag.take());
+        assert!(!second_flag.take());
+        assert!(poll(&mut first, &first_waker).is_ready());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        notify.notify_one();
+        assert!(second_flag.take());
+        assert!(poll(&mut second, &second_waker).is_ready());
+    }
+
+    #[test]
+    fn notify_one_without_waiters_is_stored() {
+        let notify = Notify::new();
+        let (_, waker) = Flag::waker();
+
+        // A single permit is stored however many notifications there are
+        notify.notify_one();
+        notify.notify_one();
+        assert!(poll(&mut notify.notified(), &waker).is_ready());
+        assert!(poll(&mut notify.notified(), &waker).is_pending());
+    }
+
+    #[test]
+    fn dropped_waiter_passes_the_notification_on() {
+        let notify = Notify::new();
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        let mut first = notify.notified();
+        let mut second = notify.notified();
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        notify.notify_one();
+        assert!(first_flag.take());
+        drop(first);
+        assert!(second_flag.take());
+        assert!(poll(&mut second, &second_waker).is_ready());
+
+        // A waiter dropped without a notification passes nothing on
+        let mut third = notify.notified();
+        assert!(poll(&mut third, &first_waker).is_pending());
+        drop(third);
+        assert!(poll(&mut notify.notified(), &second_waker).is_pending());
+    }
+
+    #[test]
+    fn notify_waiters_wakes_only_current_waiters() {
+        let notify = Notify::new();
+        let (first_flag, first_waker) = Flag::waker();
+        let (second_flag, second_waker) = Flag::waker();
+
+        let mut first = notify.notified();
+        let mut second = notify.notified();
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        notify.notify_waiters();
+        assert!(first_flag.take());
+        assert!(second_flag.take());
+        assert!(poll(&mut first, &first_waker).is_ready());
+        assert!(poll(&mut second, &second_waker).is_ready());
+        assert!(poll(&mut notify.notified(), &first_waker).is_pending());
+    }
+}
@@ -0,0 +1,201 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::core::error::{RecvError, TryRecvError};
+
+// Function to create a channel carrying a single value
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(Mutex::new(State {
+        value: None,
+        sender_alive: true,
+        receiver_alive: true,
+        receiver: None,
+        closed: None,
+    }));
+    (
+        Sender {
+            state: state.clone(),
+        },
+        Receiver { state },
+    )
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    receiver: Option<Waker>, // Task awaiting the Receiver
+    closed: Option<Waker>,   // Task waiting in Sender::closed
+}
+
+// Struct representing the sending side of a oneshot channel
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    // Function to send the value, giving it back if the receiver is gone
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if !state.receiver_alive {
+            return Err(value);
+        }
+
+        state.value = Some(value);
+        if let Some(waker) = state.receiver.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    // Check whether the receiver is gone, so the value is no longer wanted
+    pub fn is_closed(&self) -> bool {
+        !self.state.lock().unwrap().receiver_alive
+    }
+
+    // Function to wait until the receiver is gone, e.g. to stop computing the value
+    pub fn closed(&mut self) -> Closed<'_, T> {
+        Closed { sender: self }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.sender_alive = false;
+        if let Some(waker) = state.receiver.take() {
+            waker.wake();
+        }
+    }
+}
+
+// Future completing once the receiver of a oneshot channel is gone
+pub struct Closed<'a, T> {
+    sender: &'a mut Sender<T>,
+}
+
+impl<T> Future for Closed<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.sender.state.lock().unwrap();
+        if !state.receiver_alive {
+            return Poll::Ready(());
+        }
+        state.closed = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+// Struct representing the receiving side of a oneshot channel. Await it to get
+// the value, it fails with RecvError::Closed if the sender is dropped first.
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    // Function to take the value only if it was already sent
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.state.lock().unwrap();
+        match state.value.take() {
+            Some(value) => Ok(value),
+            None if !state.sender_alive => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // Function to refuse the value, the sender fails from now on
+    pub fn close(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.receiver_alive = false;
+        if let Some(waker) = state.closed.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(Err(RecvError::Closed));
+        }
+
+        state.receiver = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::channel;
+    use crate::core::error::{RecvError, TryRecvError};
+
+    #[test]
+    fn send_wakes_the_receiver() {
+        let (tx, mut rx) = channel();
+        let (flag, waker) = Flag::waker();
+
+        assert!(poll(&mut rx, &waker).is_pending());
+        tx.send(7).unwrap();
+        assert!(flag.take());
+        assert_eq!(poll(&mut rx, &waker), Poll::Ready(Ok(7)));
+    }
+
+    #[test]
+    fn dropped_sender_fails_the_receiver() {
+        let (tx, mut rx) = channel::<u32>();
+        let (flag, waker) = Flag::waker();
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert!(poll(&mut rx, &waker).is_pending());
+        drop(tx);
+        assert!(flag.take());
+        assert_eq!(poll(&mut rx, &waker), Poll::Ready(Err(RecvError::Closed)));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn closed_receiver_refuses_the_value() {
+        let (mut tx, mut rx) = channel();
+        let (flag, waker) = Flag::waker();
+
+        let mut closed = tx.closed();
+        assert!(poll(&mut closed, &waker).is_pending());
+        rx.close();
+        assert!(flag.take());
+        assert_eq!(poll(&mut closed, &waker), Poll::Ready(()));
+
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(7), Err(7));
+    }
+
+    #[test]
+    fn dropped_receiver_closes_the_channel() {
+        let (mut tx, rx) = channel::<u32>();
+        let (flag, waker) = Flag::waker();
+
+        let mut closed = tx.closed();
+        assert!(poll(&mut closed, &waker).is_pending());
+        drop(rx);
+        assert!(flag.take());
+        assert_eq!(poll(&mut closed, &waker), Poll::Ready(()));
+    }
+}
@@ -0,0 +1,240 @@
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::semaphore::{Acquire, Semaphore};
+
+// Maximum number of readers holding the lock at once. A writer takes all the
+// permits, so it waits for the readers in front of it and blocks those behind.
+const MAX_READS: usize = u32::MAX as usize >> 3;
+
+// Struct representing a reader-writer lock held across awaits. Locks are
+// granted in the order they were asked for, so writers are not starved.
+pub struct RwLock<T: ?Sized> {
+    semaphore: Semaphore, // One permit per reader, all of them for a writer
+    value: UnsafeCell<T>,
+}
+
+// The semaphore gives the value to one writer or to readers only
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        RwLock {
+            semaphore: Semaphore::new(MAX_READS),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    // Function to wait for shared access, released when the guard is dropped
+    pub fn read(&self) -> Read<'_, T> {
+        Read {
+            lock: self,
+            acquire: self.semaphore.acquire(),
+        }
+    }
+
+    // Function to wait for exclusive access, released when the guard is dropped
+    pub fn write(&self) -> Write<'_, T> {
+        Write {
+            lock: self,
+            acquire: self.semaphore.acquire_many(MAX_READS),
+        }
+    }
+
+    // Get the value through a unique reference, no locking needed
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+// Future waiting for shared access to a RwLock
+pub struct Read<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    acquire: Acquire<'a>,
+}
+
+impl<'a, T: ?Sized> Future for Read<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let read = self.get_mut();
+        match Pin::new(&mut read.acquire).poll(cx) {
+            Poll::Ready(permit) => {
+                permit.forget();
+                Poll::Ready(RwLockReadGuard { lock: read.lock })
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// Future waiting for exclusive access to a RwLock
+pub struct Write<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    acquire: Acquire<'a>,
+}
+
+impl<'a, T: ?Sized> Future for Write<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let write = self.get_mut();
+        match Pin::new(&mut write.acquire).poll(cx) {
+            Poll::Ready(permit) => {
+                permit.forget();
+                Poll::Ready(RwLockWriteGuard { lock: write.lock })
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// Guard giving shared access to the value of a RwLock
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(1);
+    }
+}
+
+// Guard giving exclusive access to the value of a RwLock
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.add_permits(MAX_READS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::RwLock;
+
+    #[test]
+    fn readers_share_the_lock() {
+        let lock = RwLock::new(1);
+        let (flag, waker) = Flag::waker();
+
+        let Poll::Ready(first) = poll(&mut lock.read(), &waker) else {
+            panic!("the lock is free");
+        };
+        let Poll::Ready(second) = poll(&mut lock.read(), &waker) else {
+            panic!("readers share the lock");
+        };
+        assert_eq!(*first + *second, 2);
+
+        let mut write = lock.write();
+        assert!(poll(&mut write, &waker).is_pending());
+        drop(first);
+        assert!(!flag.take());
+        drop(second);
+        assert!(flag.take());
+        let Poll::Ready(mut guard) = poll(&mut write, &waker) else {
+            panic!("the readers are gone");
+        };
+        *guard = 2;
+    }
+
+    #[test]
+    fn waiting_writer_holds_back_later_readers() {
+        let lock = RwLock::new(0);
+        let (writer_flag, writer_waker) = Flag::waker();
+        let (reader_flag, reader_waker) = Flag::waker();
+
+        let Poll::Ready(reader) = poll(&mut lock.read(), &reader_waker) else {
+            panic!("the lock is free");
+        };
+        let mut write = lock.write();
+        assert!(poll(&mut write, &writer_waker).is_pending());
+
+        // A reader arriving after the writer waits for it, even though the
+        // lock is only read right now
+        let mut read = lock.read();
+        assert!(poll(&mut read, &reader_waker).is_pending());
+
+        drop(reader);
+        assert!(writer_flag.take());
+        assert!(!reader_flag.take());
+        let Poll::Ready(mut writer) = poll(&mut write, &writer_waker) else {
+            panic!("the writer is next");
+        };
+        *writer = 1;
+        assert!(poll(&mut read, &reader_waker).is_pending());
+
+        drop(writer);
+        assert!(reader_flag.take());
+        let Poll::Ready(reader) = poll(&mut read, &reader_waker) else {
+            panic!("the writer is gone");
+        };
+        assert_eq!(*reader, 1);
+    }
+
+    #[test]
+    fn dropped_writer_lets_readers_in() {
+        let lock = RwLock::new(0);
+        let (writer_flag, writer_waker) = Flag::waker();
+        let (reader_flag, reader_waker) = Flag::waker();
+
+        let Poll::Ready(reader) = poll(&mut lock.read(), &reader_waker) else {
+            panic!("the lock is free");
+        };
+        let mut write = lock.write();
+        let mut read = lock.read();
+        assert!(poll(&mut write, &writer_waker).is_pending());
+        assert!(poll(&mut read, &reader_waker).is_pending());
+
+        drop(write);
+        assert!(!writer_flag.take());
+        assert!(reader_flag.take());
+        let Poll::Ready(second) = poll(&mut read, &reader_waker) else {
+            panic!("the writer gave up");
+        };
+        assert_eq!(*reader + *second, 0);
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+// Struct representing a counting semaphore. Permits are handed out in request
+// order: a task asking for many permits is not starved by tasks asking for few.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    permits: usize,            // Permits available
+    waiters: VecDeque<Waiter>, // Tasks waiting for permits, oldest first
+    next_key: u64,
+}
+
+struct Waiter {
+    key: u64,
+    needed: usize, // Permits requested
+    waker: Waker,
+    assigned: bool, // Set once the permits were handed to the waiter
+}
+
+impl Semaphore {
+    // Constructor to create a semaphore with the given number of permits
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+                next_key: 0,
+            }),
+        }
+    }
+
+    // Function to wait for a permit, released when the returned guard is dropped
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    // Function to wait for `permits` permits at once
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            needed: permits,
+            key: None,
+        }
+    }
+
+    // Function to wait for a permit not tied to the lifetime of the semaphore,
+    // so it can be moved into a spawned task
+    pub fn acquire_owned(self: Arc<Self>) -> AcquireOwned {
+        self.acquire_many_owned(1)
+    }
+
+    // Function to wait for `permits` owned permits at once
+    pub fn acquire_many_owned(self: Arc<Self>, permits: usize) -> AcquireOwned {
+        AcquireOwned {
+            semaphore: Some(self),
+            needed: permits,
+            key: None,
+        }
+    }
+
+    // Function to take a permit only if one is available without waiting
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_take(1).then(|| SemaphorePermit {
+            semaphore: self,
+            permits: 1,
+        })
+    }
+
+    // Function to take an owned permit only if one is available without waiting
+    pub fn try_acquire_owned(self: Arc<Self>) -> Option<OwnedSemaphorePermit> {
+        self.try_take(1).then(|| OwnedSemaphorePermit {
+            semaphore: self,
+            permits: 1,
+        })
+    }
+
+    // Function to add permits, waking the tasks they are enough for
+    pub fn add_permits(&self, permits: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.permits += permits;
+        state.assign();
+    }
+
+    // Get the number of permits available
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    // Take permits right away, unless someone is already waiting for some
+    fn try_take(&self, needed: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.waiters.is_empty() && state.permits >= needed {
+            state.permits -= needed;
+            return true;
+        }
+        false
+    }
+
+    // Take the permits or queue for them, resolving once they were handed over
+    fn poll_acquire(&self, needed: usize, key: &mut Option<u64>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(current) = *key else {
+            if state.waiters.is_empty() && state.permits >= needed {
+                state.permits -= needed;
+                return Poll::Ready(());
+            }
+
+            state.next_key += 1;
+            let new_key = state.next_key;
+            *key = Some(new_key);
+            state.waiters.push_back(Waiter {
+                key: new_key,
+                needed,
+                waker: cx.waker().clone(),
+                assigned: false,
+            });
+            return Poll::Pending;
+        };
+
+        let index = state
+            .waiters
+            .iter()
+            .position(|waiter| waiter.key == current)
+            .expect("acquire polled after completion");
+
+        if state.waiters[index].assigned {
+            state.waiters.remove(index);
+            *key = None;
+            return Poll::Ready(());
+        }
+
+        state.waiters[index].waker.clone_from(cx.waker());
+        Poll::Pending
+    }
+
+    // Leave the queue, giving back the permits if they were already handed over
+    fn cancel_acquire(&self, needed: usize, key: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.waiters.iter().position(|waiter| waiter.key == key) {
+            if state.waiters.remove(index).unwrap().assigned {
+                state.permits += needed;
+            }
+            // Waiters behind us may be satisfied now
+            state.assign();
+        }
+    }
+}
+
+impl State {
+    // Hand the available permits to the oldest waiters, stopping at the first
+    // one they are not enough for
+    fn assign(&mut self) {
+        for waiter in self.waiters.iter_mut() {
+            if waiter.assigned {
+                continue;
+            }
+            if waiter.needed > self.permits {
+                break;
+            }
+            self.permits -= waiter.needed;
+            waiter.assigned = true;
+            waiter.waker.wake_by_ref();
+        }
+    }
+}
+
+// Future waiting for permits of a semaphore
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    needed: usize,
+    key: Option<u64>, // Key in the waiters, once queued
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let acquire = self.get_mut();
+        match acquire
+            .semaphore
+            .poll_acquire(acquire.needed, &mut acquire.key, cx)
+        {
+            Poll::Ready(()) => Poll::Ready(SemaphorePermit {
+                semaphore: acquire.semaphore,
+                permits: acquire.needed,
+            }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.semaphore.cancel_acquire(self.needed, key);
+        }
+    }
+}
+
+// Future waiting for owned permits of a semaphore
+pub struct AcquireOwned {
+    semaphore: Option<Arc<Semaphore>>, // Taken by the permit once acquired
+    needed: usize,
+    key: Option<u64>, // Key in the waiters, once queued
+}
+
+impl Future for AcquireOwned {
+    type Output = OwnedSemaphorePermit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let acquire = self.get_mut();
+        let semaphore = acquire
+            .semaphore
+            .as_ref()
+            .expect("acquire polled after completion");
+
+        match semaphore.poll_acquire(acquire.needed, &mut acquire.key, cx) {
+            Poll::Ready(()) => Poll::Ready(OwnedSemaphorePermit {
+                semaphore: acquire.semaphore.take().unwrap(),
+                permits: acquire.needed,
+            }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for AcquireOwned {
+    fn drop(&mut self) {
+        if let (Some(semaphore), Some(key)) = (&self.semaphore, self.key) {
+            semaphore.cancel_acquire(self.needed, key);
+        }
+    }
+}
+
+// Permits borrowed from a semaphore, given back when dropped
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    // Function to keep the permits out of the semaphore for good
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+// Permits taken from a shared semaphore, given back when dropped
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+}
+
+impl OwnedSemaphorePermit {
+    // Function to keep the permits out of the semaphore for good
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    // Get the semaphore the permits belong to
+    pub fn semaphore(&self) -> &Arc<Semaphore> {
+        &self.semaphore
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::Semaphore;
+
+    #[test]
+    fn permits_are_handed_out_in_request_order() {
+        let semaphore = Semaphore::new(3);
+        let (big_flag, big_waker) = Flag::waker();
+        let (small_flag, small_waker) = Flag::waker();
+
+        let held = semaphore.try_acquire().unwrap();
+        let mut big = semaphore.acquire_many(3);
+        let mut small = semaphore.acquire();
+        assert!(poll(&mut big, &big_waker).is_pending());
+
+        // Two permits are left, but the small request waits behind the big one
+        assert!(poll(&mut small, &small_waker).is_pending());
+        assert!(semaphore.try_acquire().is_none());
+        assert_eq!(semaphore.available_permits(), 2);
+
+        drop(held);
+        assert!(big_flag.take());
+        assert!(!small_flag.take());
+        let Poll::Ready(permit) = poll(&mut big, &big_waker) else {
+            panic!("the permits were handed over");
+        };
+        assert_eq!(permit.num_permits(), 3);
+        assert!(poll(&mut small, &small_waker).is_pending());
+
+        drop(permit);
+        assert!(small_flag.take());
+        let Poll::Ready(_permit) = poll(&mut small, &small_waker) else {
+            panic!("the permit was handed over");
+        };
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn dropped_acquire_leaves_the_queue() {
+        let semaphore = Semaphore::new(1);
+        let (big_flag, big_waker) = Flag::waker();
+        let (small_flag, small_waker) = Flag::waker();
+
+        let held = semaphore.try_acquire().unwrap();
+        let mut big = semaphore.acquire_many(2);
+        let mut small = semaphore.acquire();
+        assert!(poll(&mut big, &big_waker).is_pending());
+        assert!(poll(&mut small, &small_waker).is_pending());
+
+        drop(held);
+        assert!(!small_flag.take());
+
+        // The waiter in front gives up, the one behind it gets the permit
+        drop(big);
+        assert!(!big_flag.take());
+        assert!(small_flag.take());
+        let Poll::Ready(_permit) = poll(&mut small, &small_waker) else {
+            panic!("the permit was handed over");
+        };
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+
+    #[test]
+    fn dropped_acquire_gives_back_its_permits() {
+        let semaphore = Semaphore::new(2);
+        let (flag, waker) = Flag::waker();
+
+        let held = semaphore.try_acquire().unwrap();
+        let other = semaphore.try_acquire().unwrap();
+        let mut acquire = semaphore.acquire_many(2);
+        assert!(poll(&mut acquire, &waker).is_pending());
+
+        // Handed the permits but dropped before polled again
+        drop(held);
+        assert!(!flag.take());
+        drop(other);
+        assert!(flag.take());
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(acquire);
+        assert_eq!(semaphore.available_permits(), 2);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    fn owned_permits_return_to_the_semaphore() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let (flag, waker) = Flag::waker();
+
+        let permit = semaphore.clone().try_acquire_owned().unwrap();
+        let mut acquire = semaphore.clone().acquire_owned();
+        assert!(poll(&mut acquire, &waker).is_pending());
+
+        drop(permit);
+        assert!(flag.take());
+        let Poll::Ready(permit) = poll(&mut acquire, &waker) else {
+            panic!("the permit was handed over");
+        };
+        assert_eq!(permit.semaphore().available_permits(), 0);
+        drop(permit);
+
+        // A forgotten permit is gone for good
+        semaphore.clone().try_acquire_owned().unwrap().forget();
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+}
@@ -0,0 +1,270 @@
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+use super::WaitList;
+use crate::core::error::{RecvError, SendError};
+
+// Function to create a channel holding a single value that receivers can read
+// at any time and be notified about when it changes
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value: init,
+        version: 0,
+        sender_alive: true,
+        receivers: 1,
+        waiters: WaitList::new(),
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+struct State<T> {
+    value: T,
+    version: u64, // Incremented on every change
+    sender_alive: bool,
+    receivers: usize,
+    waiters: WaitList, // Receivers waiting for a change
+}
+
+// Struct representing a borrowed value of a watch channel. The channel cannot
+// change while it is held, keep it short and never across an await.
+pub struct Ref<'a, T> {
+    guard: MutexGuard<'a, State<T>>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard.value
+    }
+}
+
+// Struct representing the sending side of a watch channel
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    // Function to replace the value and notify the receivers. Fails with the
+    // value if every receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+
+        state.value = value;
+        state.version += 1;
+        state.waiters.wake_all();
+        Ok(())
+    }
+
+    // Function to change the value in place and notify the receivers, even if
+    // there are none
+    pub fn send_modify(&self, modify: impl FnOnce(&mut T)) {
+        let mut state = self.shared.lock().unwrap();
+        modify(&mut state.value);
+        state.version += 1;
+        state.waiters.wake_all();
+    }
+
+    // Get the current value
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            guard: self.shared.lock().unwrap(),
+        }
+    }
+
+    // Function to create a receiver, the current value counts as seen
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock().unwrap();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            seen: state.version,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.sender_alive = false;
+        state.waiters.wake_all();
+    }
+}
+
+// Struct representing a receiving side of a watch channel
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+    seen: u64, // Version of the last value marked as seen
+}
+
+impl<T> Receiver<T> {
+    // Get the current value without marking it as seen
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            guard: self.shared.lock().unwrap(),
+        }
+    }
+
+    // Get the current value and mark it as seen
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        let guard = self.shared.lock().unwrap();
+        self.seen = guard.version;
+        Ref { guard }
+    }
+
+    // Check whether the value changed since it was last marked as seen
+    pub fn has_changed(&self) -> bool {
+        self.shared.lock().unwrap().version != self.seen
+    }
+
+    // Function to wait for the value to change and mark it as seen. Fails
+    // with RecvError::Closed once the sender is gone.
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed {
+            receiver: self,
+            key: None,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().unwrap().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().receivers -= 1;
+    }
+}
+
+// Future waiting for the value of a watch channel to change
+pub struct Changed<'a, T> {
+    receiver: &'a mut Receiver<T>,
+    key: Option<u64>, // Key in the waiters, once waiting
+}
+
+impl<T> Future for Changed<'_, T> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let changed = self.get_mut();
+        let receiver = &mut *changed.receiver;
+        let mut state = receiver.shared.lock().unwrap();
+
+        if state.version != receiver.seen {
+            receiver.seen = state.version;
+            return Poll::Ready(Ok(()));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(Err(RecvError::Closed));
+        }
+
+        state.waiters.register(&mut changed.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Changed<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.receiver.shared.lock().unwrap().waiters.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::super::testing::{poll, Flag};
+    use super::channel;
+    use crate::core::error::RecvError;
+
+    #[test]
+    fn changed_waits_for_a_new_value() {
+        let (tx, mut rx) = channel(0);
+        let (flag, waker) = Flag::waker();
+
+        assert!(!rx.has_changed());
+        let mut changed = rx.changed();
+        assert!(poll(&mut changed, &waker).is_pending());
+        tx.send(1).unwrap();
+        assert!(flag.take());
+        assert_eq!(poll(&mut changed, &waker), Poll::Ready(Ok(())));
+        drop(changed);
+
+        // Several changes are seen at once, as the latest value
+        tx.send(2).unwrap();
+        tx.send_modify(|value| *value += 1);
+        assert!(rx.has_changed());
+        assert_eq!(*rx.borrow_and_update(), 3);
+        assert!(!rx.has_changed());
+        assert!(poll(&mut rx.changed(), &waker).is_pending());
+    }
+
+    #[test]
+    fn subscribers_start_from_the_current_value() {
+        let (tx, rx) = channel("a");
+        tx.send("b").unwrap();
+
+        let mut late = tx.subscribe();
+        let mut clone = rx.clone();
+        assert!(!late.has_changed());
+        assert!(clone.has_changed());
+        assert_eq!(*late.borrow_and_update(), "b");
+        assert_eq!(*clone.borrow_and_update(), "b");
+        assert_eq!(*rx.borrow(), "b");
+        assert!(rx.has_changed());
+    }
+
+    #[test]
+    fn changed_fails_once_the_sender_is_gone() {
+        let (tx, mut rx) = channel(0);
+        let (flag, waker) = Flag::waker();
+
+        // A change sent before the sender is dropped is still reported
+        tx.send(1).unwrap();
+        let mut changed = rx.changed();
+        assert_eq!(poll(&mut changed, &waker), Poll::Ready(Ok(())));
+        drop(changed);
+
+        let mut changed = rx.changed();
+        assert!(poll(&mut changed, &waker).is_pending());
+        drop(tx);
+        assert!(flag.take());
+        assert_eq!(
+            poll(&mut changed, &waker),
+            Poll::Ready(Err(RecvError::Closed))
+        );
+        drop(changed);
+        assert_eq!(*rx.borrow(), 1);
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (tx, rx) = channel(0);
+        drop(rx);
+        assert_eq!(tx.send(1).unwrap_err().0, 1);
+
+        // send_modify still changes the value
+        tx.send_modify(|value| *value = 2);
+        assert_eq!(*tx.borrow(), 2);
+    }
+}
@@ -1,29 +1,226 @@
 use std::{
+    collections::HashMap,
     future::Future,
     pin::Pin,
-    sync::{mpsc::SyncSender, Arc, Mutex},
-    task::{Wake, Waker},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    task::{Context, Poll, Wake, Waker},
 };
 
+use super::coop;
+use super::metrics::PollTimes;
+use super::priority::Priority;
+
 // Define a type alias for a boxed Future that is Send and 'static
 pub type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
+// Trait implemented by the run queues a Task can be scheduled onto
+pub trait Schedule: Send + Sync {
+    fn schedule(&self, task: Arc<Task>);
+
+    // Called when a task is created for this scheduler
+    fn bind(&self, _task: &Arc<Task>) {}
+
+    // Called once the future of a task completed or was dropped
+    fn release(&self, _task: &Arc<Task>) {}
+}
+
+// What block_on does with the spawned tasks still running once its root future completes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RemainingTasks {
+    #[default]
+    Detach, // Leave them alone, they keep running (or resume with the next block_on)
+    Cancel, // Drop them, their JoinHandles resolve to JoinError::Cancelled
+    Wait,   // Keep running them until they all complete
+}
+
+// Struct representing the live tasks of a scheduler, so they can be awaited or cancelled
+#[derive(Default)]
+pub struct OwnedTasks {
+    tasks: Mutex<HashMap<usize, Arc<Task>>>, // Live tasks by address
+    waiters: Mutex<Vec<Waker>>,              // Woken once no task is left
+    spawned: AtomicUsize,                    // Tasks inserted so far
+    completed: AtomicUsize,                  // Tasks that ran to completion so far
+}
+
+impl OwnedTasks {
+    pub fn insert(&self, task: &Arc<Task>) {
+        self.tasks.lock().unwrap().insert(task.id(), task.clone());
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, task: &Arc<Task>) {
+        let empty = {
+            let mut tasks = self.tasks.lock().unwrap();
+            // Tasks taken out by cancel_all are not counted as completed
+            if tasks.remove(&task.id()).is_some() {
+                self.completed.fetch_add(1, Ordering::Relaxed);
+            }
+            tasks.is_empty()
+        };
+
+        if empty {
+            self.wake_waiters();
+        }
+    }
+
+    // Number of live tasks
+    pub fn len(&self) -> usize {
+        self.tasks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of tasks spawned since the scheduler was created
+    pub fn spawned(&self) -> usize {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
+    // Number of tasks that ran to completion since the scheduler was created
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    // Get the live tasks
+    pub fn live(&self) -> Vec<Arc<Task>> {
+        self.tasks.lock().unwrap().values().cloned().collect()
+    }
+
+    // Drop the future of every live task, resolving their JoinHandles to
+    // Cancelled. Returns the number of tasks cancelled.
+    pub fn cancel_all(&self) -> usize {
+        let tasks: Vec<Arc<Task>> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, task)| task)
+            .collect();
+        let cancelled = tasks.len();
+        for task in tasks {
+            task.cancel();
+        }
+        self.wake_waiters();
+        cancelled
+    }
+
+    // Check whether every task completed, registering the waker otherwise
+    pub fn poll_empty(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let tasks = self.tasks.lock().unwrap();
+        if tasks.is_empty() {
+            return Poll::Ready(());
+        }
+
+        // Registered with the tasks locked so a concurrent remove cannot be missed
+        self.waiters.lock().unwrap().push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn wake_waiters(&self) {
+        for waker in self.waiters.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
 // Struct representing a Task for scheduling and managing asynchronous operations
 pub struct Task {
-    pub future: Mutex<BoxedFuture<'static, ()>>, // Mutex-wrapped boxed future
-    pub sender: SyncSender<Arc<Task>>,           // Sender for sending tasks to the task queue
+    pub future: Mutex<Option<BoxedFuture<'static, ()>>>, // Boxed future, dropped once it completes
+    pub scheduler: Arc<dyn Schedule>, // Run queue the task is pushed onto when woken
+    pub priority: Priority,           // Class of the task in the run queues
+    pub poll_times: PollTimes,        // Duration of every poll of the task
+    scheduled: AtomicBool,            // Set while the task sits in a run queue
+    cancelled: AtomicBool,            // Set by cancel, the future is dropped once no poll holds it
 }
 
 impl Task {
+    // Function to create a new task wrapping the given future
+    pub fn new(
+        future: BoxedFuture<'static, ()>,
+        scheduler: Arc<dyn Schedule>,
+        priority: Priority,
+    ) -> Arc<Task> {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(future)),
+            scheduler,
+            priority,
+            poll_times: PollTimes::default(),
+            scheduled: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+        });
+        task.scheduler.bind(&task);
+        task
+    }
+
+    // Identity of the task, stable while it is alive
+    pub fn id(self: &Arc<Self>) -> usize {
+        Arc::as_ptr(self) as usize
+    }
+
     // Function to schedule the task for execution
     pub fn schedule(self: &Arc<Self>) {
-        self.sender.send(self.clone()).unwrap(); // Send a clone of the task to the task queue
+        // Skip if the task is already queued, a single poll will observe every wakeup
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.scheduler.schedule(self.clone()); // Push a clone of the task onto the run queue
+        }
     }
 
     // Function to create a Waker associated with the task
     pub fn waker(self: &Arc<Self>) -> Waker {
         Waker::from(self.clone()) // Create a Waker from a clone of the task
     }
+
+    // Function to poll the task's future once, dropping it when it completes.
+    // Returns true if the task is finished.
+    pub fn poll(self: &Arc<Self>) -> bool {
+        let waker = self.waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut slot = self.future.lock().unwrap();
+
+        // Wakeups from now on must queue the task again
+        self.scheduled.store(false, Ordering::Release);
+
+        // Every poll gets a fresh budget of operations
+        let poll = coop::budget(|| slot.as_mut().map(|future| future.as_mut().poll(&mut cx)));
+        match poll {
+            Some(Poll::Ready(())) => {
+                *slot = None; // Drop the future and everything it captured
+                drop(slot);
+                self.scheduler.release(self);
+                true
+            }
+            Some(Poll::Pending) => {
+                drop(slot);
+
+                // Cancelled while being polled, e.g. from a block_on nested in the task
+                if self.cancelled.load(Ordering::Acquire) {
+                    self.cancel();
+                }
+                self.future.lock().unwrap().is_none()
+            }
+            None => true, // Woken after completion, nothing left to poll
+        }
+    }
+
+    // Function to drop the task's future without polling it again. A task being
+    // polled, possibly by the caller itself, is dropped once that poll returns.
+    pub fn cancel(self: &Arc<Self>) {
+        self.cancelled.store(true, Ordering::Release);
+
+        let future = match self.future.try_lock() {
+            Ok(mut slot) => slot.take(),
+            Err(TryLockError::WouldBlock) => return, // The poll checks the flag once done
+            Err(TryLockError::Poisoned(err)) => err.into_inner().take(),
+        };
+        if future.is_some() {
+            drop(future); // Dropped outside the lock, it may wake other tasks
+            self.scheduler.release(self);
+        }
+    }
 }
 
 // Implement the Wake trait for Task, allowing it to be woken up
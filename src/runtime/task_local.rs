@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread::LocalKey;
+
+use crate::core::error::AccessError;
+
+// Macro declaring task-local values, set for the duration of a future with
+// TaskLocal::scope and read from anywhere it awaits:
+//
+//     task_local! {
+//         pub static REQUEST_ID: u64;
+//     }
+//
+//     REQUEST_ID.scope(42, async { REQUEST_ID.get() }).await
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty; $($rest:tt)*) => {
+        $crate::__task_local_inner!($(#[$attr])* $vis $name, $t);
+        $crate::task_local!($($rest)*);
+    };
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty) => {
+        $crate::__task_local_inner!($(#[$attr])* $vis $name, $t);
+    };
+}
+
+// Declare a single task-local value, used by task_local!
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __task_local_inner {
+    ($(#[$attr:meta])* $vis:vis $name:ident, $t:ty) => {
+        $(#[$attr])*
+        $vis static $name: $crate::runtime::task_local::TaskLocal<$t> = {
+            std::thread_local! {
+                static __KEY: std::cell::RefCell<Option<$t>> = const { std::cell::RefCell::new(None) };
+            }
+            $crate::runtime::task_local::TaskLocal { inner: &__KEY }
+        };
+    };
+}
+
+// Struct representing a task-local value. While a scope future is polled, its
+// value is moved into a thread-local slot and moved back out once the poll
+// returns, so it follows the task across worker threads. Tasks spawned from
+// the scope do not inherit the value.
+pub struct TaskLocal<T: 'static> {
+    pub inner: &'static LocalKey<RefCell<Option<T>>>, // Set by the task_local! macro
+}
+
+impl<T: 'static> TaskLocal<T> {
+    // Function to run a future with the value set, every poll of the future
+    // sees it. Scopes of the same key can be nested.
+    pub fn scope<F: Future>(&'static self, value: T, future: F) -> TaskLocalFuture<T, F> {
+        TaskLocalFuture {
+            local: self,
+            slot: Some(value),
+            future: Box::pin(future),
+        }
+    }
+
+    // Function to run a closure with the value set
+    pub fn sync_scope<R>(&'static self, value: T, f: impl FnOnce() -> R) -> R {
+        let mut slot = Some(value);
+        let _guard = Swap::enter(self, &mut slot);
+        f()
+    }
+
+    // Function to access the value. Panics outside of a scope setting it.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f)
+            .expect("task-local value accessed outside of its scope")
+    }
+
+    // Function to access the value, failing outside of a scope setting it
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        self.inner.with(|cell| match cell.borrow().as_ref() {
+            Some(value) => Ok(f(value)),
+            None => Err(AccessError),
+        })
+    }
+
+    // Function to get a copy of the value. Panics outside of a scope setting it.
+    pub fn get(&'static self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+}
+
+// Guard moving a value into the thread-local slot of a key, and the previous
+// one back out when dropped, even if the code in between panics
+struct Swap<'a, T: 'static> {
+    local: &'static TaskLocal<T>,
+    slot: &'a mut Option<T>,
+}
+
+impl<'a, T: 'static> Swap<'a, T> {
+    fn enter(local: &'static TaskLocal<T>, slot: &'a mut Option<T>) -> Self {
+        local
+            .inner
+            .with(|cell| mem::swap(&mut *cell.borrow_mut(), slot));
+        Swap { local, slot }
+    }
+}
+
+impl<T: 'static> Drop for Swap<'_, T> {
+    fn drop(&mut self) {
+        self.local
+            .inner
+            .with(|cell| mem::swap(&mut *cell.borrow_mut(), self.slot));
+    }
+}
+
+// Future running a future with a task-local value set
+pub struct TaskLocalFuture<T: 'static, F> {
+    local: &'static TaskLocal<T>,
+    slot: Option<T>, // The value, while the future is not being polled
+    future: Pin<Box<F>>,
+}
+
+impl<T: 'static, F> Unpin for TaskLocalFuture<T, F> {}
+
+impl<T: 'static, F: Future> Future for TaskLocalFuture<T, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let state = self.get_mut();
+        let _guard = Swap::enter(state.local, &mut state.slot);
+        state.future.as_mut().poll(cx)
+    }
+}
@@ -1,35 +1,165 @@
-use std::sync::{
-    mpsc::{self, Receiver, SyncSender},
-    Arc,
-};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 
+use super::priority::Priority;
 use super::task::Task;
 
-// Struct representing a TaskQueue for managing asynchronous tasks
+// Struct representing a TaskQueue for managing asynchronous tasks: one FIFO per
+// priority class, served by weighted round-robin
 pub struct TaskQueue {
-    sender: SyncSender<Arc<Task>>, // Sender for sending tasks to the queue
-    receiver: Receiver<Arc<Task>>, // Receiver for receiving tasks from the queue
+    state: Mutex<State>,
+}
+
+struct State {
+    classes: [VecDeque<Arc<Task>>; 3], // Tasks waiting, by Priority::index
+    credits: [u32; 3],                 // Picks left to every class in the current round
 }
 
 impl TaskQueue {
     // Constructor to create a new TaskQueue instance
     pub fn new() -> Self {
-        let (sender, receiver) = mpsc::sync_channel(1024); // Create a synchronous channel
-        Self { sender, receiver } // Return the TaskQueue instance
+        Self {
+            state: Mutex::new(State {
+                classes: Default::default(),
+                credits: Priority::ALL.map(Priority::weight),
+            }),
+        }
+    }
+
+    // Function to push a task (wrapped in an Arc) onto the queue of its class
+    pub fn push(&self, task: Arc<Task>) {
+        let mut state = self.state.lock().unwrap();
+        state.classes[task.priority.index()].push_back(task);
+    }
+
+    // Function to pop the next task: the highest class with tasks waiting and
+    // picks left in the round. A round ends once no class with tasks waiting
+    // has picks left, every class then gets its weight again.
+    pub fn pop(&self) -> Option<Arc<Task>> {
+        let mut state = self.state.lock().unwrap();
+
+        for _ in 0..2 {
+            for priority in Priority::ALL {
+                let index = priority.index();
+                if state.credits[index] > 0 {
+                    if let Some(task) = state.classes[index].pop_front() {
+                        state.credits[index] -= 1;
+                        return Some(task);
+                    }
+                }
+            }
+
+            state.credits = Priority::ALL.map(Priority::weight);
+        }
+
+        None
+    }
+
+    // Check whether tasks of the class are waiting
+    pub fn has_waiting(&self, priority: Priority) -> bool {
+        !self.state.lock().unwrap().classes[priority.index()].is_empty()
+    }
+
+    // Number of tasks of the class waiting
+    pub fn len_of(&self, priority: Priority) -> usize {
+        self.state.lock().unwrap().classes[priority.index()].len()
+    }
+
+    // Number of tasks waiting, every class included
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.classes.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::TaskQueue;
+    use crate::runtime::priority::Priority;
+    use crate::runtime::task::{Schedule, Task};
+
+    // Scheduler of tasks that are pushed onto a queue by hand
+    struct Unused;
+
+    impl Schedule for Unused {
+        fn schedule(&self, _task: Arc<Task>) {}
     }
 
-    // Function to send a task (wrapped in an Arc) to the queue
-    pub fn send(&self, task: Arc<Task>) {
-        self.sender.send(task).unwrap(); // Send the task to the queue (blocking if full)
+    fn push(queue: &TaskQueue, priority: Priority, count: usize) {
+        for _ in 0..count {
+            queue.push(Task::new(Box::pin(async {}), Arc::new(Unused), priority));
+        }
     }
 
-    // Function to get a clone of the sender for external use
-    pub fn sender(&self) -> SyncSender<Arc<Task>> {
-        self.sender.clone() // Return a clone of the sender
+    // Pop `count` tasks, returning how many of every class were popped
+    fn pop(queue: &TaskQueue, count: usize) -> [usize; 3] {
+        let mut popped = [0; 3];
+        for _ in 0..count {
+            popped[queue.pop().unwrap().priority.index()] += 1;
+        }
+        popped
     }
 
-    // Function to get a reference to the receiver for external use
-    pub fn receiver(&self) -> &Receiver<Arc<Task>> {
-        &self.receiver // Return a reference to the receiver
+    #[test]
+    fn classes_are_served_by_weight() {
+        let queue = TaskQueue::new();
+        for priority in Priority::ALL {
+            push(&queue, priority, 100);
+        }
+
+        // High goes first in every round, then Normal, then Low
+        let order: Vec<Priority> = (0..13).map(|_| queue.pop().unwrap().priority).collect();
+        assert!(order[..8]
+            .iter()
+            .all(|&priority| priority == Priority::High));
+        assert!(order[8..12]
+            .iter()
+            .all(|&priority| priority == Priority::Normal));
+        assert_eq!(order[12], Priority::Low);
+
+        // 8 High, 4 Normal and 1 Low per round
+        assert_eq!(pop(&queue, 13 * 5), [40, 20, 5]);
+        assert_eq!(queue.len(), 300 - 13 * 6);
+    }
+
+    #[test]
+    fn low_is_not_starved() {
+        let queue = TaskQueue::new();
+        push(&queue, Priority::Low, 10);
+
+        // Higher classes never run dry, Low still gets a pick every round
+        for _ in 0..10 {
+            push(&queue, Priority::High, 8);
+            push(&queue, Priority::Normal, 4);
+            assert_eq!(pop(&queue, 13), [8, 4, 1]);
+        }
+        assert_eq!(queue.len_of(Priority::Low), 0);
+    }
+
+    #[test]
+    fn idle_classes_leave_their_picks_to_the_others() {
+        let queue = TaskQueue::new();
+        push(&queue, Priority::Low, 20);
+        assert_eq!(pop(&queue, 20), [0, 0, 20]);
+        assert!(queue.pop().is_none());
+
+        // A class showing up mid-round is served right away
+        push(&queue, Priority::Normal, 2);
+        push(&queue, Priority::High, 1);
+        assert_eq!(queue.pop().unwrap().priority, Priority::High);
+        assert_eq!(pop(&queue, 2), [0, 2, 0]);
+        assert!(queue.is_empty());
     }
 }
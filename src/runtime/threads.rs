@@ -0,0 +1,50 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+// Callback run by the runtime threads when they start or stop
+pub type Hook = Arc<dyn Fn() + Send + Sync>;
+
+// Struct describing how the runtime names its threads and what they run
+// around their work
+#[derive(Clone)]
+pub struct ThreadConfig {
+    pub name: String,           // Prefix of the thread names
+    pub on_start: Option<Hook>, // Run on every thread before it does any work
+    pub on_stop: Option<Hook>,  // Run on every thread right before it exits
+}
+
+impl ThreadConfig {
+    // Function to start a thread named `{name}-{suffix}` running the hooks around `f`
+    pub fn spawn<F, T>(&self, suffix: &str, f: F) -> io::Result<thread::JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let on_start = self.on_start.clone();
+        let on_stop = self.on_stop.clone();
+
+        thread::Builder::new()
+            .name(format!("{}-{}", self.name, suffix))
+            .spawn(move || {
+                if let Some(hook) = on_start {
+                    hook();
+                }
+                let output = f();
+                if let Some(hook) = on_stop {
+                    hook();
+                }
+                output
+            })
+    }
+}
+
+impl Default for ThreadConfig {
+    fn default() -> Self {
+        ThreadConfig {
+            name: "toy".to_string(),
+            on_start: None,
+            on_stop: None,
+        }
+    }
+}
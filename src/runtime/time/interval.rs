@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::sleep::{now, sleep_until, Sleep};
+
+// Function to create an interval ticking every period, starting immediately
+pub fn interval(period: Duration) -> Interval {
+    interval_at(now(), period)
+}
+
+// Function to create an interval ticking every period, starting at the given instant
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "interval period must be non-zero");
+
+    Interval {
+        sleep: sleep_until(start),
+        period,
+    }
+}
+
+// Struct representing a stream of ticks spaced by a fixed period. Ticks missed
+// because the task was busy are delivered back to back until it catches up.
+pub struct Interval {
+    sleep: Sleep,     // Sleep until the next tick
+    period: Duration, // Time between two ticks
+}
+
+impl Interval {
+    // Function to wait for the next tick, resolving to the instant it was scheduled for
+    pub fn tick(&mut self) -> Tick<'_> {
+        Tick { interval: self }
+    }
+
+    // Function to poll for the next tick
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                let deadline = self.sleep.deadline();
+                self.sleep.reset(deadline + self.period);
+                Poll::Ready(deadline)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    // Function to restart the interval so the next tick happens one period from now
+    pub fn reset(&mut self) {
+        self.sleep.reset(now() + self.period);
+    }
+
+    // Get the period of the interval
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+// Future for waiting on the next tick of an interval
+pub struct Tick<'a> {
+    interval: &'a mut Interval,
+}
+
+impl<'a> Future for Tick<'a> {
+    type Output = Instant;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().interval.poll_tick(cx)
+    }
+}
@@ -0,0 +1,8 @@
+pub mod interval;
+pub mod sleep;
+pub mod timeout;
+pub mod wheel;
+
+pub use interval::{interval, interval_at, Interval};
+pub use sleep::{now, sleep, sleep_until, Sleep};
+pub use timeout::{timeout, timeout_at, Timeout};
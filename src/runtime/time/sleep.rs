@@ -0,0 +1,106 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::runtime::reactor::{Reactor, REACTOR};
+use crate::runtime::sim::{self, TimerKey};
+
+// Get the current instant: the virtual clock inside a simulation, the real one otherwise
+pub fn now() -> Instant {
+    sim::instant().unwrap_or_else(Instant::now)
+}
+
+// Function to create a future completing once the given duration has elapsed
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(now() + duration)
+}
+
+// Function to create a future completing once the given instant is reached
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        timer: None,
+    }
+}
+
+// Future for waiting until a deadline
+pub struct Sleep {
+    deadline: Instant,    // Instant at which the future completes
+    timer: Option<Timer>, // Timer registered for the deadline, if any
+}
+
+// Timer registered by a Sleep
+enum Timer {
+    Reactor(Arc<Reactor>, u64), // Reactor and key in its timer wheel, keys are per wheel
+    Simulated(TimerKey),        // Key in the timers of the simulation
+}
+
+impl Sleep {
+    // Get the instant at which the future completes
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    // Check whether the deadline has been reached
+    pub fn is_elapsed(&self) -> bool {
+        now() >= self.deadline
+    }
+
+    // Function to move the deadline, re-arming the sleep even if it already completed
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        self.cancel();
+    }
+
+    // Function to remove the registered timer from the reactor or the simulation
+    // it was registered with, whatever thread drops the sleep
+    fn cancel(&mut self) {
+        match self.timer.take() {
+            Some(Timer::Reactor(reactor, key)) => reactor.remove_timer(key),
+            Some(Timer::Simulated(key)) => sim::remove_timer(key),
+            None => {}
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        if state.is_elapsed() {
+            state.cancel();
+            return Poll::Ready(());
+        }
+
+        // Inside a simulation, register again to keep the waker of the last poll
+        if sim::is_current() {
+            state.cancel();
+            let key = sim::add_timer(state.deadline, cx.waker().clone());
+            state.timer = Some(Timer::Simulated(key));
+            return Poll::Pending;
+        }
+
+        // Register a timer with the reactor, or refresh its waker if already registered
+        match &state.timer {
+            Some(Timer::Reactor(reactor, key)) => reactor.update_timer(*key, cx),
+            _ => {
+                let reactor = REACTOR.with(|current| current.borrow().clone());
+                let key = reactor.add_timer(state.deadline, cx);
+                state.timer = Some(Timer::Reactor(reactor, key));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+// Drop implementation to cancel the timer of a sleep that did not complete
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
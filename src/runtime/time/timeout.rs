@@ -0,0 +1,52 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::sleep::{now, sleep_until, Sleep};
+use crate::core::error::Elapsed;
+
+// Function to run a future, giving up if it does not complete within the duration
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    timeout_at(now() + duration, future)
+}
+
+// Function to run a future, giving up if it does not complete before the deadline
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep_until(deadline),
+    }
+}
+
+// Future for racing a future against a deadline. The inner future is dropped
+// along with the Timeout, cancelling it if the deadline was reached first.
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+    // Get the instant at which the timeout elapses
+    pub fn deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = std::result::Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+
+        // Give the future a chance to complete even if the deadline just passed
+        if let Poll::Ready(output) = state.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match Pin::new(&mut state.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+// Number of levels in the wheel and slots per level. Every level covers 64 times
+// the range of the one below it, level 0 having a resolution of one millisecond.
+const LEVELS: usize = 6;
+const SLOTS: usize = 64;
+const SLOT_BITS: u32 = 6;
+
+// Furthest tick ahead of the wheel position that can be represented (~2.2 years)
+const MAX_TICKS: u64 = (1 << (SLOT_BITS * LEVELS as u32)) - 1;
+
+// Struct representing a timer registered in the wheel
+struct Entry {
+    tick: u64,    // Expiration, in milliseconds since the wheel start
+    level: usize, // Level the entry is currently stored in
+    slot: usize,  // Slot the entry is currently stored in
+    waker: Waker, // Waker to notify once the timer expires
+}
+
+// Struct representing a hierarchical timer wheel with millisecond resolution
+pub struct Wheel {
+    start: Instant,                 // Instant corresponding to tick 0
+    elapsed: u64,                   // Tick up to which timers have been processed
+    levels: Vec<Vec<HashSet<u64>>>, // Keys of the entries stored in every slot of every level
+    entries: HashMap<u64, Entry>,   // Registered timers by key
+    next_key: u64,                  // Key handed out to the next registered timer
+}
+
+impl Wheel {
+    // Constructor to create an empty wheel starting now
+    pub fn new() -> Self {
+        Wheel {
+            start: Instant::now(),
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| HashSet::new()).collect())
+                .collect(),
+            entries: HashMap::new(),
+            next_key: 0,
+        }
+    }
+
+    // Function to register a timer expiring at the given deadline, returning its key
+    pub fn insert(&mut self, deadline: Instant, waker: Waker) -> u64 {
+        let key = self.next_key;
+        self.next_key += 1;
+
+        let tick = self.tick_for(deadline);
+        self.place(key, tick, waker);
+        key
+    }
+
+    // Function to replace the waker of a registered timer
+    pub fn update(&mut self, key: u64, waker: &Waker) {
+        if let Some(entry) = self.entries.get_mut(&key) {
+            if !entry.waker.will_wake(waker) {
+                entry.waker = waker.clone();
+            }
+        }
+    }
+
+    // Function to remove a timer from the wheel. Unknown or fired keys are ignored.
+    pub fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.levels[entry.level][entry.slot].remove(&key);
+        }
+    }
+
+    // Number of timers currently registered
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Check whether no timer is registered
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Function to compute how long the poller may block before the next timer is due
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        let (_, _, deadline) = self.next_expiration()?;
+        let deadline = self.start + Duration::from_millis(deadline);
+        Some(deadline.saturating_duration_since(now))
+    }
+
+    // Function to advance the wheel to the given instant, returning the wakers of expired timers
+    pub fn process(&mut self, now: Instant) -> Vec<Waker> {
+        let now_tick = now.saturating_duration_since(self.start).as_millis() as u64;
+        let mut fired = Vec::new();
+
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now_tick {
+                break;
+            }
+
+            self.elapsed = deadline;
+
+            // Fire the expired entries and cascade the others down to a finer level
+            for key in mem::take(&mut self.levels[level][slot]) {
+                let entry = self.entries.remove(&key).unwrap();
+                if entry.tick <= self.elapsed {
+                    fired.push(entry.waker);
+                } else {
+                    self.place(key, entry.tick, entry.waker);
+                }
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now_tick);
+        fired
+    }
+
+    // Convert a deadline to a tick, rounding up so timers never fire early
+    fn tick_for(&self, deadline: Instant) -> u64 {
+        let since = deadline.saturating_duration_since(self.start);
+        let tick = since.as_nanos().div_ceil(1_000_000) as u64;
+        tick.clamp(self.elapsed + 1, self.elapsed + MAX_TICKS)
+    }
+
+    // Store an entry in the slot matching its tick relative to the wheel position
+    fn place(&mut self, key: u64, tick: u64, waker: Waker) {
+        // The level is given by the most significant bit that differs from the current position
+        let masked = ((self.elapsed ^ tick) | (SLOTS as u64 - 1)).min(MAX_TICKS);
+        let significant = 63 - masked.leading_zeros();
+        let level = (significant / SLOT_BITS) as usize;
+        let slot = ((tick >> (level as u32 * SLOT_BITS)) & (SLOTS as u64 - 1)) as usize;
+
+        self.levels[level][slot].insert(key);
+        self.entries.insert(
+            key,
+            Entry {
+                tick,
+                level,
+                slot,
+                waker,
+            },
+        );
+    }
+
+    // Find the next occupied slot, returning its level, index and starting tick
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        for level in 0..LEVELS {
+            let slot_range = 1u64 << (level as u32 * SLOT_BITS);
+            let level_range = slot_range << SLOT_BITS;
+            let level_start = self.elapsed & !(level_range - 1);
+            let now_slot = ((self.elapsed / slot_range) % SLOTS as u64) as usize;
+
+            for offset in 0..SLOTS {
+                let slot = (now_slot + offset) % SLOTS;
+                if self.levels[level][slot].is_empty() {
+                    continue;
+                }
+
+                // Slots behind the current one hold entries of the next rotation
+                let mut deadline = level_start + slot as u64 * slot_range;
+                if slot < now_slot || (level > 0 && slot == now_slot) {
+                    deadline += level_range;
+                }
+
+                return Some((level, slot, deadline));
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for Wheel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::task::{Wake, Waker};
+    use std::time::Duration;
+
+    use super::{Wheel, MAX_TICKS, SLOTS};
+    use crate::runtime::rand::FastRand;
+
+    // Waker recording its id once woken
+    struct Record {
+        id: u64,
+        fired: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Wake for Record {
+        fn wake(self: Arc<Self>) {
+            self.fired.lock().unwrap().push(self.id);
+        }
+    }
+
+    struct Harness {
+        wheel: Wheel,
+        fired: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Harness {
+                wheel: Wheel::new(),
+                fired: Arc::default(),
+            }
+        }
+
+        // Register a timer `ms` milliseconds after the wheel start, returning its key
+        fn insert(&mut self, id: u64, ms: u64) -> u64 {
+            let waker = Waker::from(Arc::new(Record {
+                id,
+                fired: self.fired.clone(),
+            }));
+            let deadline = self.wheel.start + Duration::from_millis(ms);
+            self.wheel.insert(deadline, waker)
+        }
+
+        // Advance the wheel to `ms` milliseconds after its start, returning the ids fired
+        fn process(&mut self, ms: u64) -> Vec<u64> {
+            let now = self.wheel.start + Duration::from_millis(ms);
+            for waker in self.wheel.process(now) {
+                waker.wake();
+            }
+            std::mem::take(&mut *self.fired.lock().unwrap())
+        }
+
+        fn next_timeout(&self, ms: u64) -> Option<Duration> {
+            self.wheel
+                .next_timeout(self.wheel.start + Duration::from_millis(ms))
+        }
+
+        fn level_of(&self, key: u64) -> usize {
+            self.wheel.entries[&key].level
+        }
+    }
+
+    #[test]
+    fn fires_within_the_first_level() {
+        let mut wheel = Harness::new();
+        wheel.insert(1, 5);
+        wheel.insert(2, 10);
+
+        assert!(wheel.process(4).is_empty());
+        assert_eq!(wheel.process(5), [1]);
+        assert!(wheel.process(9).is_empty());
+        assert_eq!(wheel.process(10), [2]);
+        assert!(wheel.wheel.is_empty());
+    }
+
+    #[test]
+    fn fires_across_levels() {
+        let mut wheel = Harness::new();
+        let level_1 = wheel.insert(1, 100);
+        let level_2 = wheel.insert(2, 5_000);
+        let level_3 = wheel.insert(3, 300_000);
+        assert_eq!(wheel.level_of(level_1), 1);
+        assert_eq!(wheel.level_of(level_2), 2);
+        assert_eq!(wheel.level_of(level_3), 3);
+
+        assert!(wheel.process(99).is_empty());
+        assert_eq!(wheel.process(100), [1]);
+        assert!(wheel.process(4_999).is_empty());
+        assert_eq!(wheel.process(5_000), [2]);
+        assert!(wheel.process(299_999).is_empty());
+        assert_eq!(wheel.process(300_000), [3]);
+    }
+
+    #[test]
+    fn cascades_at_level_boundaries() {
+        let mut wheel = Harness::new();
+        let at_boundary = wheel.insert(1, SLOTS as u64);
+        let after_boundary = wheel.insert(2, SLOTS as u64 + 1);
+        let next_level = wheel.insert(3, (SLOTS * SLOTS) as u64);
+        assert_eq!(wheel.level_of(at_boundary), 1);
+        assert_eq!(wheel.level_of(after_boundary), 1);
+        assert_eq!(wheel.level_of(next_level), 2);
+
+        assert!(wheel.process(SLOTS as u64 - 1).is_empty());
+
+        // Reaching the slot fires the due entry and moves the other one down
+        assert_eq!(wheel.process(SLOTS as u64), [1]);
+        assert_eq!(wheel.level_of(after_boundary), 0);
+        assert_eq!(wheel.process(SLOTS as u64 + 1), [2]);
+
+        assert!(wheel.process((SLOTS * SLOTS) as u64 - 1).is_empty());
+        assert_eq!(wheel.process((SLOTS * SLOTS) as u64), [3]);
+    }
+
+    #[test]
+    fn removed_timers_do_not_fire() {
+        let mut wheel = Harness::new();
+        let first = wheel.insert(1, 10);
+        let second = wheel.insert(2, 10_000);
+        wheel.insert(3, 20);
+        wheel.wheel.remove(first);
+        wheel.wheel.remove(second);
+        wheel.wheel.remove(second); // Removing twice is ignored
+        assert_eq!(wheel.wheel.len(), 1);
+
+        assert_eq!(wheel.process(100_000), [3]);
+        assert!(wheel.wheel.is_empty());
+    }
+
+    #[test]
+    fn next_timeout_points_at_the_next_slot() {
+        let mut wheel = Harness::new();
+        assert_eq!(wheel.next_timeout(0), None);
+
+        wheel.insert(1, 10);
+        assert_eq!(wheel.next_timeout(0), Some(Duration::from_millis(10)));
+        assert_eq!(wheel.next_timeout(20), Some(Duration::ZERO));
+        wheel.process(10);
+
+        // A timer stored in a coarser level wakes the poller at the start of its
+        // slot, where it cascades, then again at its deadline
+        wheel.insert(2, 100);
+        assert_eq!(wheel.next_timeout(10), Some(Duration::from_millis(54)));
+        assert!(wheel.process(64).is_empty());
+        assert_eq!(wheel.next_timeout(64), Some(Duration::from_millis(36)));
+        assert_eq!(wheel.process(100), [2]);
+    }
+
+    #[test]
+    fn clamps_deadlines() {
+        let mut wheel = Harness::new();
+        wheel.process(50);
+
+        // Past deadlines fire on the next tick, never in the tick they were added
+        let past = wheel.insert(1, 0);
+        assert_eq!(wheel.wheel.entries[&past].tick, 51);
+        assert!(wheel.process(50).is_empty());
+        assert_eq!(wheel.process(51), [1]);
+
+        // Deadlines beyond the range of the wheel are brought back to its end
+        let far = wheel.insert(2, u64::MAX / 1_000_000);
+        assert_eq!(wheel.wheel.entries[&far].tick, 51 + MAX_TICKS);
+        assert!(wheel.next_timeout(51).is_some());
+        assert!(wheel.process(50 + MAX_TICKS).is_empty());
+        assert_eq!(wheel.process(51 + MAX_TICKS), [2]);
+    }
+
+    #[test]
+    fn fires_every_timer_on_time() {
+        let mut rng = FastRand::new(7);
+        let mut wheel = Harness::new();
+        let mut deadlines = Vec::new();
+        for id in 0..500 {
+            let ms = 1 + rng.below(2_000_000) as u64;
+            wheel.insert(id, ms);
+            deadlines.push(ms);
+        }
+
+        // Advance by uneven steps, every timer must fire in the first step reaching it
+        let mut now = 0;
+        let mut fired = 0;
+        while !wheel.wheel.is_empty() {
+            let previous = now;
+            now += 1 + rng.below(5_000) as u64;
+            for id in wheel.process(now) {
+                let deadline = deadlines[id as usize];
+                assert!(
+                    deadline > previous && deadline <= now,
+                    "timer {id} fired late or early"
+                );
+                fired += 1;
+            }
+        }
+        assert_eq!(fired, 500);
+    }
+}